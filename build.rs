@@ -0,0 +1,27 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "capi")]
+    generate_header();
+}
+
+// writes atosl.h, the declarations of src/capi.rs, to OUT_DIR; the committed
+// include/atosl.h is only replaced when ATOSL_UPDATE_HEADER is set, a test
+// checks that it is up to date otherwise
+#[cfg(feature = "capi")]
+fn generate_header() {
+    let crate_dir = std::path::PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
+    let out_dir = std::path::PathBuf::from(std::env::var("OUT_DIR").unwrap());
+    println!("cargo:rerun-if-changed=src/capi.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    println!("cargo:rerun-if-env-changed=ATOSL_UPDATE_HEADER");
+    let config = cbindgen::Config::from_file(crate_dir.join("cbindgen.toml")).expect("invalid cbindgen.toml");
+    let bindings = cbindgen::Builder::new()
+        .with_config(config)
+        .with_src(crate_dir.join("src/capi.rs"))
+        .generate()
+        .expect("failed to generate atosl.h");
+    bindings.write_to_file(out_dir.join("atosl.h"));
+    if std::env::var_os("ATOSL_UPDATE_HEADER").is_some() {
+        bindings.write_to_file(crate_dir.join("include/atosl.h"));
+    }
+}
@@ -0,0 +1,178 @@
+/* Generated by cbindgen from src/capi.rs, do not edit. */
+
+#ifndef ATOSL_H
+#define ATOSL_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+/**
+ * Where a frame was found.
+ */
+typedef enum AtoslLookup {
+  ATOSL_LOOKUP_DWARF,
+  ATOSL_LOOKUP_SYMBOL_TABLE,
+} AtoslLookup;
+
+/**
+ * An opened object, created by `atosl_open` or `atosl_open_bytes` and
+ * released with `atosl_close`.
+ */
+typedef struct AtoslSymbolicator AtoslSymbolicator;
+
+/**
+ * A symbolicated address. Strings are NUL terminated UTF-8 and owned by
+ * the `AtoslResults` they belong to.
+ */
+typedef struct AtoslFrame {
+  const char *symbol;
+  const char *demangled_symbol;
+  const char *module;
+  /**
+   * NULL without line information.
+   */
+  const char *file;
+  /**
+   * `file` joined with its directories, NULL without line information.
+   */
+  const char *path;
+  /**
+   * 0 when unknown.
+   */
+  uint64_t line;
+  /**
+   * 0 when unknown.
+   */
+  uint64_t column;
+  uint64_t symbol_address;
+  uint64_t symbol_offset;
+  enum AtoslLookup lookup;
+} AtoslFrame;
+
+/**
+ * The result for one input address: `frame` when it was symbolicated,
+ * `error` otherwise.
+ */
+typedef struct AtoslResult {
+  uint64_t address;
+  /**
+   * The atos formatted frame, or the address in hex.
+   */
+  const char *result;
+  /**
+   * NULL when the address could not be symbolicated.
+   */
+  const struct AtoslFrame *frame;
+  /**
+   * The inlined call chain, innermost first and ending with the concrete
+   * function `frame` describes; empty outside of inlined functions.
+   */
+  const struct AtoslFrame *inlined;
+  size_t inlined_len;
+  /**
+   * NULL when `frame` is set.
+   */
+  const char *error;
+} AtoslResult;
+
+/**
+ * One result per input address, in order. Released with
+ * `atosl_results_free`.
+ */
+typedef struct AtoslResults {
+  const struct AtoslResult *results;
+  size_t len;
+} AtoslResults;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Opens an object file, `.dSYM` bundle, directory of dSYMs or zipped dSYM
+ * archive. `arch` and `uuid` may be NULL. Returns NULL on failure and, when
+ * `error` is not NULL, stores a message to release with
+ * `atosl_string_free` in it.
+ *
+ * # Safety
+ *
+ * `path` must be a NUL terminated string, `arch` and `uuid` NULL or NUL
+ * terminated strings, and `error` NULL or valid for writes.
+ */
+struct AtoslSymbolicator *atosl_open(const char *path,
+                                     const char *arch,
+                                     const char *uuid,
+                                     char **error);
+
+/**
+ * Opens an object or zipped dSYM archive held in memory; the data is
+ * copied. `name` is the module name results are reported under.
+ *
+ * # Safety
+ *
+ * `data` must be valid for reads of `len` bytes, `name` a NUL terminated
+ * string, `arch` and `uuid` NULL or NUL terminated strings, and `error`
+ * NULL or valid for writes.
+ */
+struct AtoslSymbolicator *atosl_open_bytes(const uint8_t *data,
+                                           size_t len,
+                                           const char *name,
+                                           const char *arch,
+                                           const char *uuid,
+                                           char **error);
+
+/**
+ * Symbolicates `len` addresses, like `atosl.parse`: with `text_segment`
+ * every address is rebased from `load_address`, where the image was loaded,
+ * onto the text segment of the object, otherwise addresses are looked up as
+ * they are. Returns NULL on failure, see `atosl_open`.
+ *
+ * # Safety
+ *
+ * `symbolicator` must come from `atosl_open` or `atosl_open_bytes` and not
+ * be closed, `addresses` must be valid for reads of `len` addresses, and
+ * `error` NULL or valid for writes.
+ */
+struct AtoslResults *atosl_symbolicate(const struct AtoslSymbolicator *symbolicator,
+                                       uint64_t load_address,
+                                       const uint64_t *addresses,
+                                       size_t len,
+                                       bool text_segment,
+                                       char **error);
+
+/**
+ * Releases results and every string and frame they point to.
+ *
+ * # Safety
+ *
+ * `results` must be NULL or come from `atosl_symbolicate`, and is not
+ * usable afterwards.
+ */
+void atosl_results_free(struct AtoslResults *results);
+
+/**
+ * Closes an object, unmapping its file.
+ *
+ * # Safety
+ *
+ * `symbolicator` must be NULL or come from `atosl_open` or
+ * `atosl_open_bytes`, and is not usable afterwards. Results of it stay
+ * valid.
+ */
+void atosl_close(struct AtoslSymbolicator *symbolicator);
+
+/**
+ * Releases an error message.
+ *
+ * # Safety
+ *
+ * `string` must be NULL or a message stored by this library.
+ */
+void atosl_string_free(char *string);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* ATOSL_H */
@@ -0,0 +1,210 @@
+use crate::macho;
+use anyhow::{anyhow, Result};
+use std::io::{self, Cursor, Read};
+
+const ZIP_MAGIC: [u8; 4] = [b'P', b'K', 0x03, 0x04];
+
+const LC_UUID: u32 = 0x1b;
+
+// the most an extracted member preallocates, larger ones grow as they are read
+const MAX_PREALLOCATION: u64 = 64 << 20;
+
+pub fn is_zip(data: &[u8]) -> bool {
+    data.starts_with(&ZIP_MAGIC)
+}
+
+// the DWARF objects of the dSYM bundles in an archive, e.g.
+// dSYMs/MyApp.app.dSYM/Contents/Resources/DWARF/MyApp
+fn is_dwarf_member(name: &str) -> bool {
+    name.contains("Contents/Resources/DWARF/") && !name.ends_with('/')
+}
+
+// reads a whole u32 in the byte order given by `big_endian`
+fn read_u32(reader: &mut dyn Read, big_endian: bool) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(if big_endian {
+        u32::from_be_bytes(bytes)
+    } else {
+        u32::from_le_bytes(bytes)
+    })
+}
+
+fn skip(reader: &mut dyn Read, count: u64) -> io::Result<()> {
+    io::copy(&mut reader.take(count), &mut io::sink())?;
+    Ok(())
+}
+
+// the LC_UUID of the Mach-O header `reader` is positioned at, reading only
+// the header and its load commands. Returns the number of bytes read.
+fn read_thin_uuid(reader: &mut dyn Read) -> Result<(Option<[u8; 16]>, u64)> {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    let (big_endian, header_size) = match magic {
+        [0xfe, 0xed, 0xfa, 0xce] => (true, 28),
+        [0xce, 0xfa, 0xed, 0xfe] => (false, 28),
+        [0xfe, 0xed, 0xfa, 0xcf] => (true, 32),
+        [0xcf, 0xfa, 0xed, 0xfe] => (false, 32),
+        _ => return Err(anyhow!("not a Mach-O object")),
+    };
+    // cputype, cpusubtype, filetype
+    skip(reader, 12)?;
+    let ncmds = read_u32(reader, big_endian)?;
+    let sizeofcmds = read_u32(reader, big_endian)?;
+    skip(reader, header_size - 24)?;
+    let mut commands = reader.take(sizeofcmds as u64);
+    let mut uuid = None;
+    for _ in 0..ncmds {
+        let cmd = read_u32(&mut commands, big_endian)?;
+        let cmdsize = read_u32(&mut commands, big_endian)?;
+        if cmd == LC_UUID {
+            let mut bytes = [0u8; 16];
+            commands.read_exact(&mut bytes)?;
+            uuid = Some(bytes);
+            break;
+        }
+        if cmdsize < 8 {
+            return Err(anyhow!("invalid load command size {}", cmdsize));
+        }
+        skip(&mut commands, cmdsize as u64 - 8)?;
+    }
+    let read = header_size + sizeofcmds as u64 - commands.limit();
+    Ok((uuid, read))
+}
+
+/// The UUIDs of a thin or universal Mach-O read from a stream, such as a
+/// compressed zip member, without reading more than the headers and load
+/// commands of each slice.
+pub fn read_uuids(reader: &mut dyn Read) -> Result<Vec<[u8; 16]>> {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    let fat64 = match magic {
+        [0xca, 0xfe, 0xba, 0xbe] => false,
+        [0xca, 0xfe, 0xba, 0xbf] => true,
+        _ => {
+            let (uuid, _) = read_thin_uuid(&mut Cursor::new(magic).chain(reader))?;
+            return Ok(uuid.into_iter().collect());
+        }
+    };
+    // fat headers are always big endian
+    let nfat_arch = read_u32(reader, true)?;
+    let mut position: u64 = 8;
+    let mut offsets = Vec::new();
+    for _ in 0..nfat_arch {
+        // cputype, cpusubtype
+        skip(reader, 8)?;
+        let offset = if fat64 {
+            let high = read_u32(reader, true)? as u64;
+            (high << 32) | read_u32(reader, true)? as u64
+        } else {
+            read_u32(reader, true)? as u64
+        };
+        // size, align (and reserved)
+        let rest = if fat64 { 8 + 8 } else { 4 + 4 };
+        skip(reader, rest)?;
+        position += if fat64 { 32 } else { 20 };
+        offsets.push(offset);
+    }
+    offsets.sort_unstable();
+    let mut uuids = Vec::new();
+    for offset in offsets {
+        if offset < position {
+            return Err(anyhow!("overlapping slices in universal file"));
+        }
+        skip(reader, offset - position)?;
+        let (uuid, read) = read_thin_uuid(reader)?;
+        position = offset + read;
+        uuids.extend(uuid);
+    }
+    Ok(uuids)
+}
+
+/// The DWARF objects of a zipped dSYM archive as (member name, uuids).
+pub fn archive_uuids(data: &[u8]) -> Result<Vec<(String, Vec<[u8; 16]>)>> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(data))?;
+    let mut members = Vec::new();
+    for i in 0..archive.len() {
+        let mut member = archive.by_index(i)?;
+        let name = member.name().to_string();
+        if !is_dwarf_member(&name) {
+            continue;
+        }
+        if let Ok(uuids) = read_uuids(&mut member) {
+            members.push((name, uuids));
+        }
+    }
+    Ok(members)
+}
+
+/// Decompresses the DWARF object of a zipped dSYM archive that contains
+/// `uuid`, or its only DWARF object when no uuid is given. Returns the file
+/// name of the object and its content.
+pub fn extract_object(data: &[u8], uuid: Option<&str>) -> Result<(String, Vec<u8>)> {
+    let members = archive_uuids(data)?;
+    let name = match uuid {
+        Some(uuid) => {
+            let uuid = macho::parse_uuid(uuid)?;
+            members
+                .iter()
+                .find(|(_, uuids)| uuids.contains(&uuid))
+                .map(|(name, _)| name.clone())
+                .ok_or_else(|| anyhow!("no dSYM with uuid {} in archive", macho::format_uuid(&uuid)))?
+        }
+        None => match members.len() {
+            0 => return Err(anyhow!("no dSYM found in archive")),
+            1 => members[0].0.clone(),
+            _ => {
+                return Err(anyhow!(
+                    "archive contains several dSYMs, specify uuid (available: {})",
+                    members
+                        .iter()
+                        .map(|(name, uuids)| {
+                            let uuids = uuids.iter().map(macho::format_uuid).collect::<Vec<_>>();
+                            format!("{} {}", name, uuids.join(" "))
+                        })
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ))
+            }
+        },
+    };
+    let mut archive = zip::ZipArchive::new(Cursor::new(data))?;
+    let mut member = archive.by_name(&name)?;
+    let mut object = Vec::with_capacity(member.size().min(MAX_PREALLOCATION) as usize);
+    member.read_to_end(&mut object)?;
+    let file_name = name.rsplit('/').next().unwrap_or(&name).to_string();
+    Ok((file_name, object))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::macho::tests::{fat, thin, ARM64};
+
+    #[test]
+    fn reads_thin_uuid() {
+        let uuids = read_uuids(&mut Cursor::new(thin(ARM64, Some([1; 16])))).unwrap();
+        assert_eq!(uuids, vec![[1; 16]]);
+        assert!(read_uuids(&mut Cursor::new(thin(ARM64, None))).unwrap().is_empty());
+    }
+
+    #[test]
+    fn reads_universal_uuids() {
+        let data = fat(&[thin(ARM64, Some([1; 16])), thin(ARM64, None), thin(ARM64, Some([2; 16]))]);
+        let mut uuids = read_uuids(&mut Cursor::new(data)).unwrap();
+        uuids.sort();
+        assert_eq!(uuids, vec![[1; 16], [2; 16]]);
+    }
+
+    #[test]
+    fn rejects_truncated_and_foreign_data() {
+        assert!(read_uuids(&mut Cursor::new(b"PK\x03\x04".to_vec())).is_err());
+        let data = thin(ARM64, Some([1; 16]));
+        assert!(read_uuids(&mut Cursor::new(data[..40].to_vec())).is_err());
+        // sizeofcmds far beyond the data fails without allocating it
+        let mut data = thin(ARM64, None);
+        data[20..24].copy_from_slice(&u32::MAX.to_le_bytes());
+        data[16..20].copy_from_slice(&100u32.to_le_bytes());
+        assert!(read_uuids(&mut Cursor::new(data)).is_err());
+    }
+}
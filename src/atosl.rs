@@ -3,133 +3,579 @@
 // email : everettjf@live.com
 // created at 2022-01-01
 //
+use crate::archive;
 use crate::demangle;
+use crate::dsym;
+use crate::elf;
+use crate::macho;
 use anyhow::{anyhow, Result};
-use gimli::{DW_TAG_subprogram, DebugInfoOffset, Dwarf, EndianSlice, RunTimeEndian};
-use object::{Object, ObjectSection, ObjectSegment};
-use std::path::Path;
-use std::{borrow, fs};
+use gimli::{
+    DW_TAG_inlined_subroutine, DW_TAG_lexical_block, DW_TAG_subprogram, DebugInfoOffset,
+    DebuggingInformationEntry, Dwarf, EndianSlice, EntriesTreeNode, FileEntry, LineProgramHeader,
+    RunTimeEndian, Unit, UnitOffset,
+};
+use object::{
+    BinaryFormat, Object, ObjectSection, ObjectSegment, ObjectSymbol, ObjectSymbolTable,
+    SectionKind, SegmentFlags, SymbolKind, SymbolMap, SymbolMapName,
+};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::{borrow, fmt, fs};
 
 pub struct ResponseResult {
     pub address: u64,
+    /// The formatted frame, or the address in hex like atos prints for
+    /// addresses it cannot symbolicate.
     pub result: String,
+    /// None when the address could not be symbolicated, see `error`.
+    pub frame: Option<Frame>,
+    /// The inlined call chain at `address`, innermost first and ending with
+    /// the concrete function `frame` describes. Empty when the address is not
+    /// inside an inlined subroutine.
+    pub inlined: Vec<Frame>,
+    pub error: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lookup {
+    Dwarf,
+    SymbolTable,
+}
+
+/// A symbolicated address split into its parts. `symbol_address` is in the
+/// object's own address space (the same space `get_search_address` maps into).
+#[derive(Clone)]
+pub struct Frame {
+    pub symbol: String,
+    pub demangled_symbol: String,
+    pub module: String,
+    pub file: Option<String>,
+    /// `file` joined with its include directory and the compilation
+    /// directory, as printed by `atos -fullPath`.
+    pub path: Option<String>,
+    pub line: Option<u64>,
+    pub column: Option<u64>,
+    pub symbol_address: u64,
+    pub symbol_offset: u64,
+    pub lookup: Lookup,
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.lookup, &self.file, self.line) {
+            // main (in BinaryName) (main.m:100)
+            (Lookup::Dwarf, Some(file), Some(line)) => write!(
+                f,
+                "{} (in {}) ({}:{})",
+                self.demangled_symbol, self.module, file, line
+            ),
+            // main (in BinaryName) + 12
+            _ => write!(
+                f,
+                "{} (in {}) + {}",
+                self.demangled_symbol, self.module, self.symbol_offset
+            ),
+        }
+    }
+}
+
+impl ResponseResult {
+    fn new(address: u64, frame: Frame, inlined: Vec<Frame>) -> ResponseResult {
+        ResponseResult {
+            address,
+            result: frame.to_string(),
+            frame: Some(frame),
+            inlined,
+            error: None,
+        }
+    }
+
+    fn error(address: u64, error: String) -> ResponseResult {
+        ResponseResult {
+            address,
+            result: format!("0x{:x}", address),
+            frame: None,
+            inlined: Vec::new(),
+            error: Some(error),
+        }
+    }
 }
 
 pub struct GroupAddress {
     pub load_address: u64,
     pub addresses: Vec<u64>,
+    /// The image these addresses belong to, overriding the uuid given for
+    /// the whole call. Picks the object when `file` is a directory of dSYMs.
+    pub uuid: Option<String>,
+}
+
+pub struct Symbolicator<'data> {
+    object: object::File<'data>,
+    symbols: SymbolTable<'data>,
+    dwarf: Option<Dwarf<borrow::Cow<'data, [u8]>>>,
+    // built on the first address .debug_aranges does not cover
+    unit_index: OnceLock<Vec<UnitRange>>,
+    object_filename: String,
+    text_vmaddr: u64,
+    // declared last so that `object` and `dwarf`, which borrow from the
+    // data, are dropped before it
+    _data: Option<ObjectData>,
+}
+
+// what a `Symbolicator<'static>` borrows from, owned by it
+enum ObjectData {
+    Mapped(memmap::Mmap),
+    Owned(Vec<u8>),
+}
+
+/// The object to symbolicate: a path (see `Symbolicator::open`), or the bytes
+/// of an object or zipped dSYM archive reported under the module `name`.
+pub enum ObjectInput<'a> {
+    Path(&'a str),
+    Bytes { data: &'a [u8], name: &'a str },
+}
+
+impl Symbolicator<'static> {
+    /// Opens and parses `object_path` once so that it can be symbolicated
+    /// against many times. `arch` and `uuid` select the slice of a universal
+    /// binary and are checked against thin files. `object_path` may also be a
+    /// `.dSYM` bundle or a directory of dSYMs (see `dsym::resolve_object`) or
+    /// a zipped dSYM archive. A stripped ELF object is replaced by its
+    /// separate debug file when one is found through `debug_dirs` (see
+    /// `elf::find_debug_file`).
+    pub fn open(
+        object_path: &str,
+        arch: Option<&str>,
+        uuid: Option<&str>,
+        debug_dirs: &[String],
+    ) -> Result<Symbolicator<'static>, anyhow::Error> {
+        let object_path = dsym::resolve_object(Path::new(object_path), uuid)?;
+        Symbolicator::open_resolved(&object_path, arch, uuid, debug_dirs)
+    }
+
+    // `open` once the object file is known
+    fn open_resolved(
+        object_path: &Path,
+        arch: Option<&str>,
+        uuid: Option<&str>,
+        debug_dirs: &[String],
+    ) -> Result<Symbolicator<'static>, anyhow::Error> {
+        match elf::find_debug_file(object_path, debug_dirs)? {
+            Some(debug_path) => Symbolicator::open_as(&debug_path, object_path, arch, uuid),
+            None => Symbolicator::open_as(object_path, object_path, arch, uuid),
+        }
+    }
+
+    /// Like `from_bytes`, taking ownership of the bytes.
+    pub fn from_vec(
+        data: Vec<u8>,
+        object_filename: &str,
+        arch: Option<&str>,
+        uuid: Option<&str>,
+    ) -> Result<Symbolicator<'static>, anyhow::Error> {
+        if archive::is_zip(&data) {
+            let (object_filename, object) = archive::extract_object(&data, uuid)?;
+            return Symbolicator::from_data(ObjectData::Owned(object), &object_filename, arch, uuid);
+        }
+        Symbolicator::from_data(ObjectData::Owned(data), object_filename, arch, uuid)
+    }
+
+    /// Parses `path` but reports results in the module named after
+    /// `module_path`, which differ for separate debug files.
+    pub fn open_as(
+        path: &Path,
+        module_path: &Path,
+        arch: Option<&str>,
+        uuid: Option<&str>,
+    ) -> Result<Symbolicator<'static>, anyhow::Error> {
+        let file = fs::File::open(path)?;
+        let mmap = unsafe { memmap::Mmap::map(&file)? };
+        if archive::is_zip(&mmap) {
+            // only the matching member is decompressed, into memory
+            let (object_filename, object) = archive::extract_object(&mmap, uuid)?;
+            return Symbolicator::from_data(ObjectData::Owned(object), &object_filename, arch, uuid);
+        }
+        let object_filename = module_path
+            .file_name()
+            .ok_or_else(|| anyhow!("file name error"))?
+            .to_str()
+            .ok_or_else(|| anyhow!("file name error(to_str)"))?;
+        Symbolicator::from_data(ObjectData::Mapped(mmap), object_filename, arch, uuid)
+    }
+
+    fn from_data(
+        data: ObjectData,
+        object_filename: &str,
+        arch: Option<&str>,
+        uuid: Option<&str>,
+    ) -> Result<Symbolicator<'static>, anyhow::Error> {
+        let bytes: &[u8] = match data {
+            ObjectData::Mapped(ref mmap) => mmap,
+            ObjectData::Owned(ref bytes) => bytes,
+        };
+        // a mapping and a vector's buffer both stay at the same address for
+        // as long as they are alive, and `data` is owned by the returned value
+        let bytes: &'static [u8] = unsafe { std::slice::from_raw_parts(bytes.as_ptr(), bytes.len()) };
+        let mut symbolicator = Symbolicator::new(bytes, object_filename, arch, uuid)?;
+        symbolicator._data = Some(data);
+        Ok(symbolicator)
+    }
 }
 
+impl<'data> Symbolicator<'data> {
+    /// Parses an object straight from borrowed bytes, reporting results in
+    /// the module `object_filename`.
+    pub fn new(
+        data: &'data [u8],
+        object_filename: &str,
+        arch: Option<&str>,
+        uuid: Option<&str>,
+    ) -> Result<Symbolicator<'data>, anyhow::Error> {
+        let object = object::File::parse(macho::select_slice(data, arch, uuid)?)?;
+        let dwarf = if is_object_dwarf(&object) {
+            Some(load_dwarf(&object)?)
+        } else {
+            None
+        };
+        let text_vmaddr = find_text_vmaddr(&object)?;
+        let symbols = object_symbol_map(&object);
+        Ok(Symbolicator {
+            object,
+            symbols,
+            dwarf,
+            unit_index: OnceLock::new(),
+            object_filename: object_filename.to_string(),
+            text_vmaddr,
+            _data: None,
+        })
+    }
+
+    /// Like `new`, also accepting a zipped dSYM archive, whose member with
+    /// `uuid` is decompressed into memory owned by the result.
+    pub fn from_bytes(
+        data: &'data [u8],
+        object_filename: &str,
+        arch: Option<&str>,
+        uuid: Option<&str>,
+    ) -> Result<Symbolicator<'data>, anyhow::Error> {
+        if archive::is_zip(data) {
+            let (object_filename, object) = archive::extract_object(data, uuid)?;
+            return Symbolicator::from_data(ObjectData::Owned(object), &object_filename, arch, uuid);
+        }
+        Symbolicator::new(data, object_filename, arch, uuid)
+    }
+
+    pub fn load(
+        input: &ObjectInput<'data>,
+        arch: Option<&str>,
+        uuid: Option<&str>,
+        debug_dirs: &[String],
+    ) -> Result<Symbolicator<'data>, anyhow::Error> {
+        match *input {
+            ObjectInput::Path(object_path) => Symbolicator::open(object_path, arch, uuid, debug_dirs),
+            ObjectInput::Bytes { data, name } => Symbolicator::from_bytes(data, name, arch, uuid),
+        }
+    }
+
+    /// The address `symbolicate` rebases addresses onto when
+    /// `file_offset_type` is set: the `__TEXT` segment of Mach-O objects,
+    /// the load bias (normally 0) of ELF objects.
+    pub fn text_vmaddr(&self) -> u64 {
+        self.text_vmaddr
+    }
+
+    pub fn symbolicate(
+        &self,
+        load_address: u64,
+        addresses: Vec<u64>,
+        file_offset_type: bool,
+    ) -> Result<Vec<ResponseResult>, anyhow::Error> {
+        match self.dwarf {
+            Some(ref dwarf_cow) => {
+                let endian = object_endian(&self.object);
+                let dwarf = dwarf_cow.borrow(|section| EndianSlice::new(section, endian));
+                dwarf_symbolize_addresses(
+                    &self.symbols,
+                    &dwarf,
+                    &self.unit_index,
+                    &self.object_filename,
+                    load_address,
+                    addresses,
+                    self.text_vmaddr,
+                    file_offset_type,
+                )
+            }
+            None => symbol_symbolize_addresses(
+                &self.symbols,
+                &self.object_filename,
+                load_address,
+                addresses,
+                self.text_vmaddr,
+                file_offset_type,
+            ),
+        }
+    }
+}
+
+/// Symbolicates every group of `addresses` against `object_path`, which may
+/// be a directory of dSYMs or a zipped dSYM archive searched by each group's
+/// uuid. See `Symbolicator::load` for `debug_dirs`.
 pub fn parse_file_addresses(
     object_path: &str,
+    arch: Option<&str>,
+    uuid: Option<&str>,
+    debug_dirs: &[String],
     addresses: Vec<GroupAddress>,
     file_offset_type: bool,
 ) -> Result<Vec<ResponseResult>, anyhow::Error> {
-    let file = fs::File::open(&object_path)?;
-    let mmap = unsafe { memmap::Mmap::map(&file)? };
-    let object = object::File::parse(&*mmap)?;
-    let object_filename = Path::new(&object_path)
-        .file_name()
-        .ok_or(anyhow!("file name error"))?
-        .to_str()
-        .ok_or(anyhow!("file name error(to_str)"))?;
+    parse_addresses(
+        &ObjectInput::Path(object_path),
+        arch,
+        uuid,
+        debug_dirs,
+        addresses,
+        file_offset_type,
+    )
+}
+
+pub fn parse_addresses<'data>(
+    input: &ObjectInput<'data>,
+    arch: Option<&str>,
+    uuid: Option<&str>,
+    debug_dirs: &[String],
+    addresses: Vec<GroupAddress>,
+    file_offset_type: bool,
+) -> Result<Vec<ResponseResult>, anyhow::Error> {
+    // a directory of dSYMs is read once for all of the groups
+    let dsyms = match *input {
+        ObjectInput::Path(path) if Path::new(path).is_dir() => Some(dsym::DsymIndex::open(Path::new(path))?),
+        _ => None,
+    };
+    // groups of the same image share one parsed object
+    let mut symbolicators: HashMap<Option<String>, Symbolicator<'data>> = HashMap::new();
     let mut results: Vec<ResponseResult> = Vec::new();
-    addresses.into_iter().for_each(|grouped| {
-        let result: Result<Vec<ResponseResult>>;
-        if is_object_dwarf(&object) {
-            result = dwarf_symbolize_addresses(
-                &object,
-                object_filename,
-                grouped.load_address,
-                grouped.addresses,
-                file_offset_type,
-            );
-        } else {
-            result = symbol_symbolize_addresses(
-                &object,
-                object_filename,
-                grouped.load_address,
-                grouped.addresses,
-                file_offset_type,
-            );
-        }
+    for grouped in addresses {
+        let group_uuid = grouped.uuid.is_some();
+        let uuid = grouped.uuid.as_deref().or(uuid);
+        let symbolicator = match symbolicators.entry(uuid.map(str::to_string)) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let loaded = match dsyms {
+                    Some(ref dsyms) => dsyms
+                        .resolve(uuid)
+                        .and_then(|path| Symbolicator::open_resolved(&path, arch, uuid, debug_dirs)),
+                    None => Symbolicator::load(input, arch, uuid, debug_dirs),
+                };
+                match loaded {
+                    Ok(symbolicator) => entry.insert(symbolicator),
+                    // an image of its own that cannot be found only fails
+                    // its own addresses
+                    Err(err) if group_uuid => {
+                        let error = err.to_string();
+                        results.extend(
+                            grouped
+                                .addresses
+                                .into_iter()
+                                .map(|address| ResponseResult::error(address, error.clone())),
+                        );
+                        continue;
+                    }
+                    Err(err) => return Err(err),
+                }
+            }
+        };
+        let result = symbolicator.symbolicate(
+            grouped.load_address,
+            grouped.addresses.clone(),
+            file_offset_type,
+        );
         match result {
             Ok(r) => {
                 results.extend(r);
             }
             Err(err) => {
-                println!("Error: {}", err);
+                let error = err.to_string();
+                results.extend(
+                    grouped
+                        .addresses
+                        .into_iter()
+                        .map(|address| ResponseResult::error(address, error.clone())),
+                );
             }
         }
-    });
+    }
     Ok(results)
 }
 
+/// Parses a `0x` prefixed hex or a decimal address.
+pub fn parse_address_string(address: &str) -> Result<u64, anyhow::Error> {
+    if address.starts_with("0x") {
+        let value = address.trim_start_matches("0x");
+        let value = u64::from_str_radix(value, 16)?;
+        Ok(value)
+    } else {
+        let value = address.parse::<u64>()?;
+        Ok(value)
+    }
+}
+
+/// Parses an address printed in hex, with or without `0x`, as atos reads
+/// them and crash reports print them.
+pub fn parse_hex_address(address: &str) -> Result<u64> {
+    let digits = address
+        .strip_prefix("0x")
+        .or_else(|| address.strip_prefix("0X"))
+        .unwrap_or(address);
+    u64::from_str_radix(digits, 16).map_err(|err| anyhow!("invalid address {}: {}", address, err))
+}
+
 pub fn print_addresses(
-    object_path: &str,
+    input: &ObjectInput,
+    arch: Option<&str>,
+    uuid: Option<&str>,
     load_address: u64,
     addresses: Vec<u64>,
     file_offset_type: bool,
 ) -> Result<Vec<ResponseResult>, anyhow::Error> {
-    let file = fs::File::open(&object_path)?;
-    let mmap = unsafe { memmap::Mmap::map(&file)? };
-    let object = object::File::parse(&*mmap)?;
-    let object_filename = Path::new(&object_path)
-        .file_name()
-        .ok_or(anyhow!("file name error"))?
-        .to_str()
-        .ok_or(anyhow!("file name error(to_str)"))?;
-
-    if is_object_dwarf(&object) {
-        return dwarf_symbolize_addresses(
-            &object,
-            object_filename,
-            load_address,
-            addresses,
-            file_offset_type,
-        );
-    } else {
-        return symbol_symbolize_addresses(
-            &object,
-            object_filename,
-            load_address,
-            addresses,
-            file_offset_type,
-        );
-    }
+    Symbolicator::load(input, arch, uuid, &[])?.symbolicate(
+        load_address,
+        addresses,
+        file_offset_type,
+    )
 }
 
+// section names are given in their ELF form, object maps them to the
+// `__debug_*` sections of Mach-O files
 fn is_object_dwarf(object: &object::File) -> bool {
-    if let Some(_) = object.section_by_name("__debug_line") {
-        true
+    object.section_by_name(".debug_line").is_some() || object.section_by_name(".debug_info").is_some()
+}
+
+fn object_endian(object: &object::File) -> RunTimeEndian {
+    if object.is_little_endian() {
+        RunTimeEndian::Little
     } else {
-        false
+        RunTimeEndian::Big
+    }
+}
+
+fn load_dwarf<'data>(
+    object: &object::File<'data>,
+) -> Result<Dwarf<borrow::Cow<'data, [u8]>>, anyhow::Error> {
+    let dwarf_cow = gimli::Dwarf::load(|section_id| -> Result<borrow::Cow<[u8]>, gimli::Error> {
+        // println!("section id = {}", section_id.name());
+        match object.section_by_name(section_id.name()) {
+            Some(ref section) => Ok(section
+                .uncompressed_data()
+                .unwrap_or(borrow::Cow::Borrowed(&[][..]))),
+            None => Ok(borrow::Cow::Borrowed(&[][..])),
+        }
+    })?;
+    Ok(dwarf_cow)
+}
+
+// find vmaddr for __TEXT segment, or for ELF the load bias, so that the load
+// address is the image base for both
+fn find_text_vmaddr(object: &object::File) -> Result<u64, anyhow::Error> {
+    for segment in object.segments() {
+        match segment.flags() {
+            // the first PT_LOAD maps file offset 0 to the image base
+            SegmentFlags::Elf { .. } => {
+                let (offset, _) = segment.file_range();
+                return Ok(segment.address().saturating_sub(offset));
+            }
+            _ => {
+                if let Some(name) = segment.name()? {
+                    if name == "__TEXT" {
+                        return Ok(segment.address());
+                    }
+                }
+            }
+        }
+    }
+    Ok(0)
+}
+
+// the symbols the symbol table search picks from
+struct SymbolTable<'data> {
+    map: SymbolMap<SymbolMapName<'data>>,
+    // the end of every symbol whose size is known, by address
+    ends: HashMap<u64, u64>,
+    // the executable sections, an address outside of them has no symbol
+    text: Vec<(u64, u64)>,
+}
+
+impl<'data> SymbolTable<'data> {
+    // the symbol covering `address`: the closest one at or before it, as
+    // long as `address` is neither past its size nor outside the code
+    fn get(&self, address: u64) -> Option<&SymbolMapName<'data>> {
+        if !self.text.is_empty() && !self.text.iter().any(|&(begin, end)| address >= begin && address < end) {
+            return None;
+        }
+        let symbol = self.map.get(address)?;
+        match self.ends.get(&symbol.address()) {
+            Some(&end) if address >= end => None,
+            _ => Some(symbol),
+        }
+    }
+}
+
+// ELF files get the functions of both .symtab and .dynsym, stripped shared
+// libraries only keep .dynsym. Other formats use object's own symbol map,
+// whose symbols carry no size.
+fn object_symbol_map<'data>(object: &object::File<'data>) -> SymbolTable<'data> {
+    let text = object
+        .sections()
+        .filter(|section| section.kind() == SectionKind::Text && section.size() > 0)
+        .map(|section| (section.address(), section.address().saturating_add(section.size())))
+        .collect();
+    if object.format() != BinaryFormat::Elf {
+        return SymbolTable {
+            map: object.symbol_map(),
+            ends: HashMap::new(),
+            text,
+        };
+    }
+    let mut symbols = Vec::new();
+    let mut ends: HashMap<u64, u64> = HashMap::new();
+    for table in [object.symbol_table(), object.dynamic_symbol_table()].iter().flatten() {
+        for symbol in table.symbols() {
+            if !symbol.is_definition() || symbol.kind() != SymbolKind::Text {
+                continue;
+            }
+            if let Ok(name) = symbol.name() {
+                if !name.is_empty() {
+                    symbols.push(SymbolMapName::new(symbol.address(), name));
+                    // st_size, 0 for symbols of hand written code
+                    if symbol.size() > 0 {
+                        let end = symbol.address().saturating_add(symbol.size());
+                        let known = ends.entry(symbol.address()).or_insert(end);
+                        *known = (*known).max(end);
+                    }
+                }
+            }
+        }
+    }
+    SymbolTable {
+        map: SymbolMap::new(symbols),
+        ends,
+        text,
     }
 }
 
 fn symbol_symbolize_addresses(
-    object: &object::File,
+    symbols: &SymbolTable,
     object_filename: &str,
     load_address: u64,
     addresses: Vec<u64>,
+    text_vmaddr: u64,
     file_offset_type: bool,
 ) -> Result<Vec<ResponseResult>, anyhow::Error> {
     let mut vec_result: Vec<ResponseResult> = Vec::new();
-    // find vmaddr for __TEXT segment
-    let mut segments = object.segments();
-    let mut text_vmaddr = 0;
-    while let Some(segment) = segments.next() {
-        if let Some(name) = segment.name()? {
-            if name == "__TEXT" {
-                text_vmaddr = segment.address();
-                break;
-            }
-        }
-    }
-
     for address in addresses {
         let symbol_result = symbol_symbolize_address(
-            &object,
+            symbols,
             object_filename,
             load_address,
             address,
@@ -138,7 +584,7 @@ fn symbol_symbolize_addresses(
         );
         match symbol_result {
             Ok(symbol) => vec_result.push(symbol),
-            Err(err) => println!("N/A - {}", err),
+            Err(err) => vec_result.push(ResponseResult::error(address, err.to_string())),
         };
     }
     Ok(vec_result)
@@ -150,23 +596,31 @@ fn get_search_address(
     text_vmaddr: u64,
     offset: bool,
 ) -> Result<u64, anyhow::Error> {
-    return match address.checked_sub(load_address) {
+    match address.checked_sub(load_address) {
         Some(subed_address) => {
             if offset {
-                return match subed_address.checked_add(text_vmaddr) {
+                match subed_address.checked_add(text_vmaddr) {
                     Some(d) => Ok(d),
-                    None => Err(anyhow!("add text_vmaddr overflow")),
-                };
+                    None => Err(anyhow!(
+                        "address 0x{:x} overflows when rebased on the text segment at 0x{:x}",
+                        address,
+                        text_vmaddr
+                    )),
+                }
             } else {
                 Ok(address)
             }
         }
-        None => Err(anyhow!("sub load_address overflow")),
-    };
+        None => Err(anyhow!(
+            "address 0x{:x} is below load address 0x{:x}",
+            address,
+            load_address
+        )),
+    }
 }
 
 fn symbol_symbolize_address(
-    object: &object::File,
+    symbols: &SymbolTable,
     object_filename: &str,
     load_address: u64,
     address: u64,
@@ -174,67 +628,44 @@ fn symbol_symbolize_address(
     file_offset_type: bool,
 ) -> Result<ResponseResult, anyhow::Error> {
     let search_address: u64 =
-        match get_search_address(address, load_address, text_vmaddr, file_offset_type) {
-            Ok(d) => d,
-            Err(err) => return Err(err),
-        };
-    let symbols = object.symbol_map();
+        get_search_address(address, load_address, text_vmaddr, file_offset_type)?;
     let found_symbol = symbols.get(search_address);
 
     if let Some(found_symbol) = found_symbol {
-        // expect format
-        // main (in BinaryName)
-        let offset = search_address - found_symbol.address();
-        let demangled_name = demangle::demangle_symbol(found_symbol.name());
-        let symbolize_result = format!("{} (in {}) + {}", demangled_name, object_filename, offset);
-        return Ok(ResponseResult {
-            address,
-            result: symbolize_result,
-        });
+        let frame = Frame {
+            symbol: found_symbol.name().to_string(),
+            demangled_symbol: demangle::demangle_symbol(found_symbol.name()),
+            module: object_filename.to_string(),
+            file: None,
+            path: None,
+            line: None,
+            column: None,
+            symbol_address: found_symbol.address(),
+            symbol_offset: search_address - found_symbol.address(),
+            lookup: Lookup::SymbolTable,
+        };
+        return Ok(ResponseResult::new(address, frame, Vec::new()));
     }
 
-    Err(anyhow!("failed search symbol"))
+    Err(anyhow!("no symbol covers 0x{:x}", search_address))
 }
 
+#[allow(clippy::too_many_arguments)]
 fn dwarf_symbolize_addresses(
-    object: &object::File,
+    symbols: &SymbolTable,
+    dwarf: &Dwarf<EndianSlice<RunTimeEndian>>,
+    unit_index: &OnceLock<Vec<UnitRange>>,
     object_filename: &str,
     load_address: u64,
     addresses: Vec<u64>,
+    text_vmaddr: u64,
     file_offset_type: bool,
 ) -> Result<Vec<ResponseResult>, anyhow::Error> {
     let mut vec_result: Vec<ResponseResult> = Vec::new();
-    let endian = if object.is_little_endian() {
-        gimli::RunTimeEndian::Little
-    } else {
-        gimli::RunTimeEndian::Big
-    };
-    let dwarf_cow = gimli::Dwarf::load(|section_id| -> Result<borrow::Cow<[u8]>, gimli::Error> {
-        // println!("section id = {}", section_id.name());
-        match object.section_by_name(section_id.name()) {
-            Some(ref section) => Ok(section
-                .uncompressed_data()
-                .unwrap_or(borrow::Cow::Borrowed(&[][..]))),
-            None => Ok(borrow::Cow::Borrowed(&[][..])),
-        }
-    })?;
-    let dwarf = dwarf_cow.borrow(|section| gimli::EndianSlice::new((&*section).as_ref(), endian));
-
-    // find vmaddr for __TEXT segment
-    let mut segments = object.segments();
-    let mut text_vmaddr = 0;
-    while let Some(segment) = segments.next() {
-        if let Some(name) = segment.name()? {
-            if name == "__TEXT" {
-                text_vmaddr = segment.address();
-                break;
-            }
-        }
-    }
-
     for address in addresses {
         let symbol_result = dwarf_symbolize_address(
-            &dwarf,
+            dwarf,
+            unit_index,
             object_filename,
             load_address,
             address,
@@ -242,14 +673,11 @@ fn dwarf_symbolize_addresses(
             file_offset_type,
         );
         match symbol_result {
-            Ok(symbol) => vec_result.push(ResponseResult {
-                address,
-                result: symbol,
-            }),
-            Err(_) => {
+            Ok((frame, inlined)) => vec_result.push(ResponseResult::new(address, frame, inlined)),
+            Err(dwarf_err) => {
                 // downgrade to symbol table search
                 let symbol_result = symbol_symbolize_address(
-                    &object,
+                    symbols,
                     object_filename,
                     load_address,
                     address,
@@ -258,27 +686,34 @@ fn dwarf_symbolize_addresses(
                 );
                 match symbol_result {
                     Ok(symbol) => vec_result.push(symbol),
-                    Err(err) => println!("N/A - {}", err),
+                    Err(err) => {
+                        let (dwarf_err, err) = (dwarf_err.to_string(), err.to_string());
+                        // both searches reject an address outside the image the same way
+                        let error = if dwarf_err == err {
+                            err
+                        } else {
+                            format!("{}; {}", dwarf_err, err)
+                        };
+                        vec_result.push(ResponseResult::error(address, error));
+                    }
                 };
             }
         };
     }
-    return Ok(vec_result);
+    Ok(vec_result)
 }
 
 fn dwarf_symbolize_address(
     dwarf: &Dwarf<EndianSlice<RunTimeEndian>>,
+    unit_index: &OnceLock<Vec<UnitRange>>,
     object_filename: &str,
     load_address: u64,
     address: u64,
     text_vmaddr: u64,
     file_offset_type: bool,
-) -> Result<String, anyhow::Error> {
+) -> Result<(Frame, Vec<Frame>), anyhow::Error> {
     let search_address: u64 =
-        match get_search_address(address, load_address, text_vmaddr, file_offset_type) {
-            Ok(d) => d,
-            Err(err) => return Err(err),
-        };
+        get_search_address(address, load_address, text_vmaddr, file_offset_type)?;
 
     // aranges
     let mut debug_info_offset: Option<DebugInfoOffset> = None;
@@ -302,9 +737,15 @@ fn dwarf_symbolize_address(
         }
     }
 
+    // .debug_aranges is optional and often partial, fall back to the
+    // ranges and line programs of the units themselves
+    if debug_info_offset.is_none() {
+        debug_info_offset = find_unit_in_index(dwarf, unit_index, search_address)?;
+    }
+
     let debug_info_offset = match debug_info_offset {
         Some(offset) => offset,
-        None => return Err(anyhow!("can not find arange")),
+        None => return Err(anyhow!("no compile unit (arange) covers 0x{:x}", search_address)),
     };
 
     // get debug info
@@ -315,97 +756,440 @@ fn dwarf_symbolize_address(
     let mut debug_info_entries = debug_info_unit.entries();
 
     let mut found_symbol_name: Option<String> = None;
-    while let Some(_) = debug_info_entries.next_entry()? {
+    let mut found_low_pc: u64 = 0;
+    let mut found_offset: Option<UnitOffset> = None;
+    while debug_info_entries.next_entry()?.is_some() {
         if let Some(entry) = debug_info_entries.current() {
             if entry.tag() == DW_TAG_subprogram {
-                let mut low_pc: Option<u64> = None;
-                let mut high_pc: Option<u64> = None;
-                if let Ok(Some(gimli::AttributeValue::Addr(lowpc_val))) =
-                    entry.attr_value(gimli::DW_AT_low_pc)
+                if let Some(low_pc) =
+                    die_contains_address(dwarf, &debug_info_unit, entry, search_address)?
                 {
-                    low_pc = Some(lowpc_val);
-                    let high_pc_value = entry.attr_value(gimli::DW_AT_high_pc);
-
-                    if let Ok(Some(high_pc_data)) = high_pc_value {
-                        if let gimli::AttributeValue::Addr(addr) = high_pc_data {
-                            high_pc = Some(addr);
-                        } else if let gimli::AttributeValue::Udata(size) = high_pc_data {
-                            high_pc = Some(lowpc_val + size);
-                        }
-                    }
-                }
-
-                if let (Some(low_pc), Some(high_pc)) = (low_pc, high_pc) {
-                    if search_address >= low_pc && search_address < high_pc {
-                        if let Ok(Some(name)) = entry.attr_value(gimli::DW_AT_name) {
-                            if let Ok(symbol_name) = dwarf.attr_string(&debug_info_unit, name) {
-                                found_symbol_name = Some(symbol_name.to_string_lossy().to_string());
-                                break;
-                            }
-                        }
+                    if let Some(symbol_name) = die_name(dwarf, &debug_info_unit, entry)? {
+                        found_symbol_name = Some(symbol_name);
+                        found_low_pc = low_pc;
+                        found_offset = Some(entry.offset());
+                        break;
                     }
                 }
             }
         }
     }
 
-    let mut found_file_name: Option<String> = None;
+    let mut inlined_subroutines: Vec<InlinedSubroutine> = Vec::new();
+    if let Some(offset) = found_offset {
+        let mut tree = debug_info_unit.entries_tree(Some(offset))?;
+        find_inlined_subroutines(
+            dwarf,
+            &debug_info_unit,
+            tree.root()?,
+            search_address,
+            &mut inlined_subroutines,
+        )?;
+    }
+
+    let mut found_file_name: Option<SourceFile> = None;
     let mut found_line: Option<u64> = None;
+    let mut found_column: Option<u64> = None;
     if let Some(program) = debug_info_unit.line_program.clone() {
         let mut rows = program.rows();
-        let mut last_file_name: Option<String> = None;
+        let mut last_file_name: Option<SourceFile> = None;
         let mut last_line: Option<u64> = None;
+        let mut last_column: Option<u64> = None;
+        // rows only describe addresses up to the next row of the same
+        // sequence; a unit with several ranges has one sequence per range
+        let mut last_address: Option<u64> = None;
         while let Some((header, row)) = rows.next_row()? {
-            if row.end_sequence() {
-                if search_address < row.address() {
+            if let Some(last_address) = last_address {
+                if search_address >= last_address && search_address < row.address() {
                     // got last filename and line
                     found_file_name = last_file_name.clone();
                     found_line = last_line;
+                    found_column = last_column;
                     if let Some(line_no) = found_line {
                         if line_no > 0 {
                             break;
                         }
                     }
                 }
+            }
+            if row.end_sequence() {
+                last_address = None;
                 continue;
             }
+            last_address = Some(row.address());
             if let Some(file) = row.file(header) {
-                let filename = dwarf
-                    .attr_string(&debug_info_unit, file.path_name())?
-                    .to_string_lossy();
-                last_file_name = Some(filename.into_owned());
+                last_file_name = Some(source_file(dwarf, &debug_info_unit, header, file)?);
             }
-            let line = match row.line() {
+            last_line = Some(match row.line() {
                 Some(line) => line.get(),
                 None => 0,
+            });
+            last_column = match row.column() {
+                gimli::ColumnType::LeftEdge => None,
+                gimli::ColumnType::Column(column) => Some(column.get()),
             };
-
-            if search_address < row.address() {
-                // got last filename and line
-                found_file_name = last_file_name.clone();
-                found_line = last_line;
-                if let Some(line_no) = found_line {
-                    if line_no > 0 {
-                        break;
-                    }
-                }
-            }
-            last_line = Some(line);
         }
     }
 
     if let (Some(symbol_name), Some(file_name), Some(line)) =
         (found_symbol_name, found_file_name, found_line)
     {
-        // expect format
-        // main (in BinaryName) (main.m:100)
+        let dwarf_frame = |symbol: String, low_pc: u64, location: Location| {
+            let demangled_name = demangle::demangle_symbol(&symbol);
+            Frame {
+                symbol,
+                demangled_symbol: demangled_name,
+                module: object_filename.to_string(),
+                file: location.0.as_ref().map(|file| file.name.clone()),
+                path: location.0.map(|file| file.path),
+                line: location.1,
+                column: location.2,
+                symbol_address: low_pc,
+                symbol_offset: search_address - low_pc,
+                lookup: Lookup::Dwarf,
+            }
+        };
 
-        let demangled_name = demangle::demangle_symbol(&symbol_name);
-        let symbolize_result = format!(
-            "{} (in {}) ({}:{})",
-            demangled_name, object_filename, file_name, line
+        // innermost first, like `atos -i`: the innermost inlined function is
+        // at the line table location, every caller is at the call site
+        // recorded on the inlined subroutine it contains
+        let mut inlined: Vec<Frame> = Vec::new();
+        if !inlined_subroutines.is_empty() {
+            let mut location: Location = (Some(file_name.clone()), Some(line), found_column);
+            for inlined_subroutine in inlined_subroutines.into_iter().rev() {
+                inlined.push(dwarf_frame(
+                    inlined_subroutine.name,
+                    inlined_subroutine.low_pc,
+                    location,
+                ));
+                location = inlined_subroutine.call_location;
+            }
+            inlined.push(dwarf_frame(symbol_name.clone(), found_low_pc, location));
+        }
+
+        let frame = dwarf_frame(
+            symbol_name,
+            found_low_pc,
+            (Some(file_name), Some(line), found_column),
         );
-        return Ok(symbolize_result);
+        return Ok((frame, inlined));
+    }
+    Err(anyhow!("no subprogram with line info covers 0x{:x}", search_address))
+}
+
+// a file of the line table, as recorded and as a full path
+#[derive(Clone)]
+struct SourceFile {
+    name: String,
+    path: String,
+}
+
+// (file, line, column)
+type Location = (Option<SourceFile>, Option<u64>, Option<u64>);
+
+fn source_file(
+    dwarf: &Dwarf<EndianSlice<RunTimeEndian>>,
+    unit: &Unit<EndianSlice<RunTimeEndian>>,
+    header: &LineProgramHeader<EndianSlice<RunTimeEndian>>,
+    file: &FileEntry<EndianSlice<RunTimeEndian>>,
+) -> Result<SourceFile, anyhow::Error> {
+    let name = dwarf.attr_string(unit, file.path_name())?.to_string_lossy().into_owned();
+    // an absolute directory or name replaces what comes before it
+    let mut path = PathBuf::new();
+    if let Some(comp_dir) = unit.comp_dir {
+        path.push(comp_dir.to_string_lossy().as_ref());
+    }
+    if let Some(directory) = file.directory(header) {
+        path.push(dwarf.attr_string(unit, directory)?.to_string_lossy().as_ref());
+    }
+    path.push(&name);
+    Ok(SourceFile {
+        name,
+        path: path.to_string_lossy().into_owned(),
+    })
+}
+
+struct InlinedSubroutine {
+    name: String,
+    low_pc: u64,
+    call_location: Location,
+}
+
+// Returns the entry address of `entry` if any of its address ranges, given
+// either as DW_AT_low_pc/DW_AT_high_pc or as a DW_AT_ranges list (.debug_ranges
+// or DWARF 5 .debug_rnglists), covers `address`.
+fn die_contains_address(
+    dwarf: &Dwarf<EndianSlice<RunTimeEndian>>,
+    unit: &Unit<EndianSlice<RunTimeEndian>>,
+    entry: &DebuggingInformationEntry<EndianSlice<RunTimeEndian>>,
+    address: u64,
+) -> Result<Option<u64>, anyhow::Error> {
+    let mut ranges = dwarf.die_ranges(unit, entry)?;
+    let mut first_begin: Option<u64> = None;
+    let mut found_range_begin: Option<u64> = None;
+    while let Some(range) = ranges.next()? {
+        if first_begin.is_none() {
+            first_begin = Some(range.begin);
+        }
+        if address >= range.begin && address < range.end {
+            found_range_begin = Some(range.begin);
+        }
+    }
+    let found_range_begin = match found_range_begin {
+        Some(begin) => begin,
+        None => return Ok(None),
+    };
+    let low_pc = match entry.attr_value(gimli::DW_AT_low_pc)? {
+        Some(value) => dwarf.attr_address(unit, value)?,
+        None => None,
+    };
+    // a cold part placed before the entry point is reported relative to
+    // the start of its own range
+    match low_pc.or(first_begin) {
+        Some(entry_pc) if entry_pc <= address => Ok(Some(entry_pc)),
+        _ => Ok(Some(found_range_begin)),
+    }
+}
+
+struct UnitRange {
+    begin: u64,
+    end: u64,
+    debug_info_offset: DebugInfoOffset,
+}
+
+// Address ranges of every unit, from DW_AT_low_pc/DW_AT_high_pc/DW_AT_ranges
+// on the unit DIE, or from the line program sequences when the unit DIE has
+// no address attributes at all. Sorted by `begin`.
+fn build_unit_index(
+    dwarf: &Dwarf<EndianSlice<RunTimeEndian>>,
+) -> Result<Vec<UnitRange>, anyhow::Error> {
+    let mut unit_index: Vec<UnitRange> = Vec::new();
+    let mut headers = dwarf.units();
+    while let Some(header) = headers.next()? {
+        let debug_info_offset = match header.offset().as_debug_info_offset() {
+            Some(offset) => offset,
+            None => continue,
+        };
+        let unit = dwarf.unit(header)?;
+        let mut found_range = false;
+        let mut ranges = dwarf.unit_ranges(&unit)?;
+        while let Some(range) = ranges.next()? {
+            if range.begin < range.end {
+                unit_index.push(UnitRange {
+                    begin: range.begin,
+                    end: range.end,
+                    debug_info_offset,
+                });
+                found_range = true;
+            }
+        }
+        if found_range {
+            continue;
+        }
+        if let Some(program) = unit.line_program.clone() {
+            let (_, sequences) = program.sequences()?;
+            for sequence in sequences {
+                if sequence.start < sequence.end {
+                    unit_index.push(UnitRange {
+                        begin: sequence.start,
+                        end: sequence.end,
+                        debug_info_offset,
+                    });
+                }
+            }
+        }
+    }
+    unit_index.sort_by_key(|range| range.begin);
+    Ok(unit_index)
+}
+
+fn find_unit_in_index(
+    dwarf: &Dwarf<EndianSlice<RunTimeEndian>>,
+    unit_index: &OnceLock<Vec<UnitRange>>,
+    address: u64,
+) -> Result<Option<DebugInfoOffset>, anyhow::Error> {
+    let unit_index = match unit_index.get() {
+        Some(unit_index) => unit_index,
+        None => {
+            let built = build_unit_index(dwarf)?;
+            unit_index.get_or_init(|| built)
+        }
+    };
+    let candidates = unit_index.partition_point(|range| range.begin <= address);
+    Ok(unit_index[..candidates]
+        .iter()
+        .rev()
+        .find(|range| address < range.end)
+        .map(|range| range.debug_info_offset))
+}
+
+// DW_AT_name, following DW_AT_abstract_origin / DW_AT_specification for
+// inlined and out-of-line instances that do not carry a name themselves
+fn die_name(
+    dwarf: &Dwarf<EndianSlice<RunTimeEndian>>,
+    unit: &Unit<EndianSlice<RunTimeEndian>>,
+    entry: &DebuggingInformationEntry<EndianSlice<RunTimeEndian>>,
+) -> Result<Option<String>, anyhow::Error> {
+    if let Some(name) = entry.attr_value(gimli::DW_AT_name)? {
+        if let Ok(symbol_name) = dwarf.attr_string(unit, name) {
+            return Ok(Some(symbol_name.to_string_lossy().to_string()));
+        }
+    }
+    for origin_attr in [gimli::DW_AT_abstract_origin, gimli::DW_AT_specification] {
+        let origin_offset = match entry.attr_value(origin_attr)? {
+            Some(gimli::AttributeValue::UnitRef(offset)) => offset,
+            Some(gimli::AttributeValue::DebugInfoRef(offset)) => {
+                match offset.to_unit_offset(&unit.header) {
+                    Some(offset) => offset,
+                    None => continue,
+                }
+            }
+            _ => continue,
+        };
+        let origin = unit.entry(origin_offset)?;
+        if let Some(name) = die_name(dwarf, unit, &origin)? {
+            return Ok(Some(name));
+        }
+    }
+    Ok(None)
+}
+
+// collects the DW_TAG_inlined_subroutine chain covering `search_address`
+// below `node`, outermost first
+fn find_inlined_subroutines(
+    dwarf: &Dwarf<EndianSlice<RunTimeEndian>>,
+    unit: &Unit<EndianSlice<RunTimeEndian>>,
+    node: EntriesTreeNode<EndianSlice<RunTimeEndian>>,
+    search_address: u64,
+    inlined_subroutines: &mut Vec<InlinedSubroutine>,
+) -> Result<(), anyhow::Error> {
+    let mut children = node.children();
+    while let Some(child) = children.next()? {
+        let entry = child.entry();
+        let tag = entry.tag();
+        if tag != DW_TAG_inlined_subroutine && tag != DW_TAG_lexical_block {
+            continue;
+        }
+        let low_pc = match die_contains_address(dwarf, unit, entry, search_address)? {
+            Some(low_pc) => low_pc,
+            None => continue,
+        };
+        if tag == DW_TAG_inlined_subroutine {
+            inlined_subroutines.push(InlinedSubroutine {
+                name: die_name(dwarf, unit, entry)?.unwrap_or_else(|| "??".to_string()),
+                low_pc,
+                call_location: call_location(dwarf, unit, entry)?,
+            });
+        }
+        return find_inlined_subroutines(dwarf, unit, child, search_address, inlined_subroutines);
+    }
+    Ok(())
+}
+
+fn call_location(
+    dwarf: &Dwarf<EndianSlice<RunTimeEndian>>,
+    unit: &Unit<EndianSlice<RunTimeEndian>>,
+    entry: &DebuggingInformationEntry<EndianSlice<RunTimeEndian>>,
+) -> Result<Location, anyhow::Error> {
+    let mut file_name: Option<SourceFile> = None;
+    if let Some(gimli::AttributeValue::FileIndex(index)) = entry.attr_value(gimli::DW_AT_call_file)? {
+        if let Some(ref program) = unit.line_program {
+            if let Some(file) = program.header().file(index) {
+                file_name = Some(source_file(dwarf, unit, program.header(), file)?);
+            }
+        }
+    }
+    let line = entry.attr(gimli::DW_AT_call_line)?.and_then(|attr| attr.udata_value());
+    let column = entry.attr(gimli::DW_AT_call_column)?.and_then(|attr| attr.udata_value());
+    Ok((file_name, line, column))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // an ELF fixture of tests/fixtures, see build.sh there for the source of
+    // the addresses used below
+    fn fixture(name: &str) -> Symbolicator<'static> {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name);
+        Symbolicator::open(path.to_str().unwrap(), None, None, &[]).unwrap()
+    }
+
+    fn symbolicate(symbolicator: &Symbolicator, address: u64) -> ResponseResult {
+        symbolicator.symbolicate(0, vec![address], false).unwrap().remove(0)
+    }
+
+    #[test]
+    fn expands_inlined_chains_innermost_first() {
+        for name in ["libfixture-dwarf4.so", "libfixture-dwarf5.so"] {
+            // `sink = x` of inner, inlined through middle into outer
+            let result = symbolicate(&fixture(name), 0x1157);
+            let chain: Vec<(&str, Option<u64>)> = result
+                .inlined
+                .iter()
+                .map(|frame| (frame.symbol.as_str(), frame.line))
+                .collect();
+            assert_eq!(chain, vec![("inner", Some(6)), ("middle", Some(11)), ("outer", Some(15))]);
+            let frame = result.frame.unwrap();
+            assert_eq!((frame.symbol.as_str(), frame.line), ("outer", Some(6)));
+            assert_eq!(frame.lookup, Lookup::Dwarf);
+        }
+    }
+
+    #[test]
+    fn leaves_the_chain_empty_outside_inlined_code() {
+        let result = symbolicate(&fixture("libfixture-dwarf5.so"), 0x1060);
+        assert!(result.inlined.is_empty());
+        assert_eq!(result.frame.unwrap().symbol, "fail");
+    }
+
+    #[test]
+    fn matches_both_ranges_of_split_functions() {
+        // DW_AT_ranges through .debug_ranges and through .debug_rnglists
+        for name in ["libfixture-dwarf4.so", "libfixture-dwarf5.so"] {
+            let symbolicator = fixture(name);
+            // hot.cold, placed before the entry point of hot, is reported
+            // relative to its own range
+            let cold = symbolicate(&symbolicator, 0x1077).frame.unwrap();
+            assert_eq!((cold.symbol.as_str(), cold.line), ("hot", Some(25)));
+            assert_eq!((cold.symbol_address, cold.symbol_offset), (0x106f, 8));
+            let hot = symbolicate(&symbolicator, 0x1178).frame.unwrap();
+            assert_eq!((hot.symbol.as_str(), hot.line), ("hot", Some(28)));
+            assert_eq!((hot.symbol_address, hot.symbol_offset), (0x1170, 8));
+        }
+    }
+
+    #[test]
+    fn finds_units_without_aranges() {
+        let symbolicator = fixture("libfixture-noaranges.so");
+        assert!(symbolicator.object.section_by_name(".debug_aranges").is_none());
+        // both ranges of the unit: .text and .text.unlikely
+        for (address, symbol, line) in [(0x1157, "outer", 6), (0x1077, "hot", 25), (0x1060, "fail", 18)] {
+            let frame = symbolicate(&symbolicator, address).frame.unwrap();
+            assert_eq!((frame.symbol.as_str(), frame.line), (symbol, Some(line)));
+            assert_eq!(frame.lookup, Lookup::Dwarf);
+        }
+        assert!(symbolicator.unit_index.get().is_some());
+        // with aranges the index is never built
+        let symbolicator = fixture("libfixture-dwarf5.so");
+        symbolicate(&symbolicator, 0x1157);
+        assert!(symbolicator.unit_index.get().is_none());
+    }
+
+    #[test]
+    fn bounds_symbol_table_matches() {
+        let symbolicator = fixture("libfixture-nodebug.so");
+        for (address, symbol, offset) in [(0x1157, "outer", 7), (0x1077, "hot.cold", 8), (0x1180, "_fini", 4)] {
+            let frame = symbolicate(&symbolicator, address).frame.unwrap();
+            assert_eq!((frame.symbol.as_str(), frame.symbol_offset), (symbol, offset));
+            assert_eq!(frame.lookup, Lookup::SymbolTable);
+        }
+        // past the 23 bytes of outer, past the end of .fini and far outside
+        // the image
+        for address in [0x1167, 0x1190, 0xffffff] {
+            let result = symbolicate(&symbolicator, address);
+            assert!(result.frame.is_none());
+            assert_eq!(result.error, Some(format!("no symbol covers 0x{:x}", address)));
+        }
     }
-    Err(anyhow!("failed search symbol"))
 }
@@ -0,0 +1,131 @@
+// A JSON-lines symbolication server for services that do not run Node:
+//
+//   atosl-server [-listen tcp:<host>:<port> | -listen unix:<path>] [-cache <objects>]
+//
+// Requests are read from stdin and answered on stdout unless -listen is
+// given, in which case every connection is served on its own thread and all
+// connections share the cache of opened objects. See `server::Server` for
+// the request and response format.
+use anyhow::{anyhow, Result};
+use atosl_node::server::{Server, DEFAULT_CACHE_SIZE};
+use std::env;
+use std::io::{self, BufReader};
+use std::net::TcpListener;
+use std::process;
+use std::sync::Arc;
+use std::thread;
+
+const USAGE: &str = "usage: atosl-server [-listen tcp:<host>:<port> | -listen unix:<path>] [-cache <objects>]";
+
+struct Options {
+    listen: Option<String>,
+    cache_size: usize,
+}
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> Result<Options> {
+    let mut options = Options {
+        listen: None,
+        cache_size: DEFAULT_CACHE_SIZE,
+    };
+    while let Some(arg) = args.next() {
+        let mut value = |flag: &str| args.next().ok_or_else(|| anyhow!("{} requires a value\n{}", flag, USAGE));
+        match arg.as_str() {
+            "-listen" => options.listen = Some(value("-listen")?),
+            "-cache" => {
+                let cache_size = value("-cache")?;
+                options.cache_size = cache_size
+                    .parse()
+                    .map_err(|err| anyhow!("invalid -cache {}: {}", cache_size, err))?;
+            }
+            "-h" | "-help" | "--help" => return Err(anyhow!("{}", USAGE)),
+            _ => return Err(anyhow!("unknown option {}\n{}", arg, USAGE)),
+        }
+    }
+    Ok(options)
+}
+
+fn serve_tcp(server: Arc<Server>, address: &str) -> Result<()> {
+    let listener = TcpListener::bind(address)?;
+    for stream in listener.incoming() {
+        // a failed accept, e.g. a client that reset the connection before
+        // it was accepted, only loses that client
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                eprintln!("atosl-server: {}", err);
+                continue;
+            }
+        };
+        let server = server.clone();
+        thread::spawn(move || {
+            if let Ok(reader) = stream.try_clone() {
+                // a client going away only ends its own connection
+                let _ = server.serve(BufReader::new(reader), stream);
+            }
+        });
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn serve_unix(server: Arc<Server>, path: &str) -> Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::net::UnixListener;
+    // a socket left behind by an earlier run would fail the bind, anything
+    // else at the path is left alone
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if metadata.file_type().is_socket() {
+            std::fs::remove_file(path)?;
+        }
+    }
+    let listener = UnixListener::bind(path)?;
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                eprintln!("atosl-server: {}", err);
+                continue;
+            }
+        };
+        let server = server.clone();
+        thread::spawn(move || {
+            if let Ok(reader) = stream.try_clone() {
+                let _ = server.serve(BufReader::new(reader), stream);
+            }
+        });
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn serve_unix(_server: Arc<Server>, _path: &str) -> Result<()> {
+    Err(anyhow!("unix sockets are not supported on this platform"))
+}
+
+fn run() -> Result<()> {
+    let options = parse_options(env::args().skip(1))?;
+    let server = Arc::new(Server::new(options.cache_size));
+    match options.listen.as_deref() {
+        None => {
+            let stdin = io::stdin();
+            server.serve(stdin.lock(), io::stdout())?;
+            Ok(())
+        }
+        Some(listen) => {
+            if let Some(address) = listen.strip_prefix("tcp:") {
+                serve_tcp(server, address)
+            } else if let Some(path) = listen.strip_prefix("unix:") {
+                serve_unix(server, path)
+            } else {
+                Err(anyhow!("-listen must be tcp:<host>:<port> or unix:<path>\n{}", USAGE))
+            }
+        }
+    }
+}
+
+fn main() {
+    if let Err(err) = run() {
+        eprintln!("atosl-server: {}", err);
+        process::exit(1);
+    }
+}
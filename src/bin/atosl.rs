@@ -0,0 +1,203 @@
+// An atos compatible command line front end to the same engine as the Node
+// module, for shell scripts and CI:
+//
+//   atosl -o <binary> [-arch <arch>] [-l <load address> | -s <slide>] [-offset] [-i] [-fullPath] [address ...]
+//
+// Addresses are read from the arguments, or line by line from stdin when
+// none are given. Every address prints one line, or one line per frame of
+// its inlined call chain with -i.
+use anyhow::{anyhow, Result};
+use atosl_node::atosl::{parse_hex_address, Frame, ResponseResult, Symbolicator};
+use std::env;
+use std::io::{self, BufRead, Write};
+use std::process;
+
+const USAGE: &str = "usage: atosl -o <binary> [-arch <arch>] [-l <load address> | -s <slide>] [-offset] [-i] [-fullPath] [address ...]";
+
+struct Options {
+    object: String,
+    arch: Option<String>,
+    load_address: Option<u64>,
+    slide: Option<u64>,
+    offset: bool,
+    inlined: bool,
+    full_path: bool,
+    addresses: Vec<u64>,
+}
+
+impl Options {
+    // the load address and text segment flag of `Symbolicator::symbolicate`
+    // for an object whose text segment is at `text_vmaddr`
+    fn search(&self, text_vmaddr: u64) -> Result<(u64, bool)> {
+        if self.offset {
+            // offsets from the start of the image
+            Ok((0, true))
+        } else if let Some(load_address) = self.load_address {
+            Ok((load_address, true))
+        } else if let Some(slide) = self.slide {
+            // address - slide is the address in the file, which is where
+            // the text segment lands when loaded at slide + text_vmaddr
+            let load_address = slide
+                .checked_add(text_vmaddr)
+                .ok_or_else(|| anyhow!("slide 0x{:x} overflows the text segment address", slide))?;
+            Ok((load_address, true))
+        } else {
+            // addresses in the file
+            Ok((0, false))
+        }
+    }
+}
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> Result<Options> {
+    let mut object = None;
+    let mut options = Options {
+        object: String::new(),
+        arch: None,
+        load_address: None,
+        slide: None,
+        offset: false,
+        inlined: false,
+        full_path: false,
+        addresses: Vec::new(),
+    };
+    while let Some(arg) = args.next() {
+        let mut value = |flag: &str| args.next().ok_or_else(|| anyhow!("{} requires a value\n{}", flag, USAGE));
+        match arg.as_str() {
+            "-o" => object = Some(value("-o")?),
+            "-arch" => options.arch = Some(value("-arch")?),
+            "-l" => options.load_address = Some(parse_hex_address(&value("-l")?)?),
+            "-s" => options.slide = Some(parse_hex_address(&value("-s")?)?),
+            "-offset" => options.offset = true,
+            "-i" => options.inlined = true,
+            "-fullPath" => options.full_path = true,
+            "-h" | "-help" | "--help" => return Err(anyhow!("{}", USAGE)),
+            _ if arg.starts_with('-') => return Err(anyhow!("unknown option {}\n{}", arg, USAGE)),
+            _ => options.addresses.push(parse_hex_address(&arg)?),
+        }
+    }
+    if options.load_address.is_some() && options.slide.is_some() {
+        return Err(anyhow!("-l and -s cannot be used together\n{}", USAGE));
+    }
+    options.object = object.ok_or_else(|| anyhow!("missing -o <binary>\n{}", USAGE))?;
+    Ok(options)
+}
+
+fn format_frame(frame: &Frame, full_path: bool) -> String {
+    if full_path && frame.path.is_some() {
+        let mut frame = frame.clone();
+        frame.file = frame.path.clone();
+        frame.to_string()
+    } else {
+        frame.to_string()
+    }
+}
+
+fn print_results(out: &mut impl Write, options: &Options, results: &[ResponseResult]) -> io::Result<()> {
+    for result in results {
+        match result.frame {
+            // innermost first, ending with the concrete function
+            Some(_) if options.inlined && !result.inlined.is_empty() => {
+                for frame in &result.inlined {
+                    writeln!(out, "{}", format_frame(frame, options.full_path))?;
+                }
+            }
+            Some(ref frame) => writeln!(out, "{}", format_frame(frame, options.full_path))?,
+            // like atos, an address that cannot be symbolicated prints as is
+            None => writeln!(out, "{}", result.result)?,
+        }
+    }
+    Ok(())
+}
+
+fn run() -> Result<()> {
+    let options = parse_options(env::args().skip(1))?;
+    let symbolicator = Symbolicator::open(&options.object, options.arch.as_deref(), None, &[])?;
+    let (load_address, file_offset_type) = options.search(symbolicator.text_vmaddr())?;
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+
+    if !options.addresses.is_empty() {
+        let results = symbolicator.symbolicate(load_address, options.addresses.clone(), file_offset_type)?;
+        print_results(&mut out, &options, &results)?;
+        return Ok(());
+    }
+
+    for line in io::stdin().lock().lines() {
+        let line = line?;
+        let mut addresses = Vec::new();
+        for word in line.split_whitespace() {
+            match parse_hex_address(word) {
+                Ok(address) => addresses.push(address),
+                Err(err) => eprintln!("atosl: {}", err),
+            }
+        }
+        let results = symbolicator.symbolicate(load_address, addresses, file_offset_type)?;
+        print_results(&mut out, &options, &results)?;
+        // answer each line right away when driven interactively
+        out.flush()?;
+    }
+    Ok(())
+}
+
+fn main() {
+    if let Err(err) = run() {
+        eprintln!("atosl: {}", err);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(args: &[&str]) -> Result<Options> {
+        parse_options(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parses_flags_and_hex_addresses() {
+        let options = options(&["-o", "MyApp", "-arch", "arm64", "-i", "-fullPath", "0x10", "20"]).unwrap();
+        assert_eq!(options.object, "MyApp");
+        assert_eq!(options.arch.as_deref(), Some("arm64"));
+        assert!(options.inlined && options.full_path && !options.offset);
+        assert_eq!(options.addresses, vec![0x10, 0x20]);
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert!(options(&["0x10"]).is_err());
+        assert!(options(&["-o"]).is_err());
+        assert!(options(&["-o", "MyApp", "-x"]).is_err());
+        assert!(options(&["-o", "MyApp", "0xzz"]).is_err());
+        assert!(options(&["-o", "MyApp", "-l", "1", "-s", "2"]).is_err());
+    }
+
+    #[test]
+    fn file_addresses_by_default() {
+        let options = options(&["-o", "MyApp"]).unwrap();
+        assert_eq!(options.search(0x100000000).unwrap(), (0, false));
+    }
+
+    #[test]
+    fn load_address_is_where_the_text_segment_was_loaded() {
+        let options = options(&["-o", "MyApp", "-l", "0x104000000"]).unwrap();
+        assert_eq!(options.search(0x100000000).unwrap(), (0x104000000, true));
+    }
+
+    #[test]
+    fn slide_is_subtracted() {
+        let options = options(&["-o", "MyApp", "-s", "0x4000000"]).unwrap();
+        let (load_address, text_segment) = options.search(0x100000000).unwrap();
+        assert!(text_segment);
+        // rebased as address - load_address + text_vmaddr
+        let address: u64 = 0x104001158;
+        assert_eq!(address - load_address + 0x100000000, address - 0x4000000);
+        assert!(options.search(u64::MAX).is_err());
+    }
+
+    #[test]
+    fn offsets_are_from_the_text_segment() {
+        let options = options(&["-o", "MyApp", "-offset", "-l", "0x104000000"]).unwrap();
+        assert_eq!(options.search(0x100000000).unwrap(), (0, true));
+    }
+}
@@ -0,0 +1,319 @@
+// The C interface, built with the `capi` feature. `build.rs` generates
+// include/atosl.h from this file with cbindgen.
+use crate::atosl::{Frame, Lookup, ResponseResult, Symbolicator};
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::{ptr, slice};
+
+/// An opened object, created by `atosl_open` or `atosl_open_bytes` and
+/// released with `atosl_close`.
+pub struct AtoslSymbolicator {
+    symbolicator: Symbolicator<'static>,
+}
+
+/// Where a frame was found.
+#[repr(C)]
+pub enum AtoslLookup {
+    Dwarf,
+    SymbolTable,
+}
+
+/// A symbolicated address. Strings are NUL terminated UTF-8 and owned by
+/// the `AtoslResults` they belong to.
+#[repr(C)]
+pub struct AtoslFrame {
+    pub symbol: *const c_char,
+    pub demangled_symbol: *const c_char,
+    pub module: *const c_char,
+    /// NULL without line information.
+    pub file: *const c_char,
+    /// `file` joined with its directories, NULL without line information.
+    pub path: *const c_char,
+    /// 0 when unknown.
+    pub line: u64,
+    /// 0 when unknown.
+    pub column: u64,
+    pub symbol_address: u64,
+    pub symbol_offset: u64,
+    pub lookup: AtoslLookup,
+}
+
+/// The result for one input address: `frame` when it was symbolicated,
+/// `error` otherwise.
+#[repr(C)]
+pub struct AtoslResult {
+    pub address: u64,
+    /// The atos formatted frame, or the address in hex.
+    pub result: *const c_char,
+    /// NULL when the address could not be symbolicated.
+    pub frame: *const AtoslFrame,
+    /// The inlined call chain, innermost first and ending with the concrete
+    /// function `frame` describes; empty outside of inlined functions.
+    pub inlined: *const AtoslFrame,
+    pub inlined_len: usize,
+    /// NULL when `frame` is set.
+    pub error: *const c_char,
+}
+
+/// One result per input address, in order. Released with
+/// `atosl_results_free`.
+#[repr(C)]
+pub struct AtoslResults {
+    pub results: *const AtoslResult,
+    pub len: usize,
+}
+
+// owns everything the pointers of `results` point into; `results` is the
+// first field so that a pointer to it is a pointer to the whole
+#[repr(C)]
+struct OwnedResults {
+    results: AtoslResults,
+    _items: Box<[AtoslResult]>,
+    _frames: Vec<Box<[AtoslFrame]>>,
+    _strings: Vec<CString>,
+}
+
+// C strings cannot hold NUL, drop any
+fn c_string(strings: &mut Vec<CString>, value: &str) -> *const c_char {
+    let value = CString::new(value.replace('\0', "")).unwrap_or_default();
+    let pointer = value.as_ptr();
+    strings.push(value);
+    pointer
+}
+
+fn c_frame(strings: &mut Vec<CString>, frame: &Frame) -> AtoslFrame {
+    AtoslFrame {
+        symbol: c_string(strings, &frame.symbol),
+        demangled_symbol: c_string(strings, &frame.demangled_symbol),
+        module: c_string(strings, &frame.module),
+        file: match frame.file {
+            Some(ref file) => c_string(strings, file),
+            None => ptr::null(),
+        },
+        path: match frame.path {
+            Some(ref path) => c_string(strings, path),
+            None => ptr::null(),
+        },
+        line: frame.line.unwrap_or(0),
+        column: frame.column.unwrap_or(0),
+        symbol_address: frame.symbol_address,
+        symbol_offset: frame.symbol_offset,
+        lookup: match frame.lookup {
+            Lookup::Dwarf => AtoslLookup::Dwarf,
+            Lookup::SymbolTable => AtoslLookup::SymbolTable,
+        },
+    }
+}
+
+fn c_results(results: Vec<ResponseResult>) -> *mut AtoslResults {
+    let mut strings = Vec::new();
+    let mut frames = Vec::new();
+    let mut items = Vec::with_capacity(results.len());
+    for result in &results {
+        // a boxed slice does not move when `frames` grows
+        let frame: Box<[AtoslFrame]> = result.frame.iter().map(|frame| c_frame(&mut strings, frame)).collect();
+        let inlined: Box<[AtoslFrame]> = result.inlined.iter().map(|frame| c_frame(&mut strings, frame)).collect();
+        items.push(AtoslResult {
+            address: result.address,
+            result: c_string(&mut strings, &result.result),
+            frame: frame.first().map_or(ptr::null(), |frame| frame as *const AtoslFrame),
+            inlined: if inlined.is_empty() { ptr::null() } else { inlined.as_ptr() },
+            inlined_len: inlined.len(),
+            error: match result.error {
+                Some(ref error) => c_string(&mut strings, error),
+                None => ptr::null(),
+            },
+        });
+        frames.push(frame);
+        frames.push(inlined);
+    }
+    let items = items.into_boxed_slice();
+    let owned = Box::new(OwnedResults {
+        results: AtoslResults {
+            results: if items.is_empty() { ptr::null() } else { items.as_ptr() },
+            len: items.len(),
+        },
+        _items: items,
+        _frames: frames,
+        _strings: strings,
+    });
+    Box::into_raw(owned) as *mut AtoslResults
+}
+
+// reports `message` through the optional `error` out parameter
+unsafe fn set_error(error: *mut *mut c_char, message: &str) {
+    if !error.is_null() {
+        let message = CString::new(message.replace('\0', "")).unwrap_or_default();
+        *error = message.into_raw();
+    }
+}
+
+unsafe fn optional_str<'a>(value: *const c_char) -> Result<Option<&'a str>, String> {
+    if value.is_null() {
+        return Ok(None);
+    }
+    CStr::from_ptr(value)
+        .to_str()
+        .map(Some)
+        .map_err(|err| format!("invalid UTF-8 argument: {}", err))
+}
+
+// runs `f`, turning its errors and panics into NULL and `error`
+unsafe fn catch<T>(error: *mut *mut c_char, f: impl FnOnce() -> Result<*mut T, String>) -> *mut T {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(value)) => value,
+        Ok(Err(message)) => {
+            set_error(error, &message);
+            ptr::null_mut()
+        }
+        Err(_) => {
+            set_error(error, "atosl panicked");
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Opens an object file, `.dSYM` bundle, directory of dSYMs or zipped dSYM
+/// archive. `arch` and `uuid` may be NULL. Returns NULL on failure and, when
+/// `error` is not NULL, stores a message to release with
+/// `atosl_string_free` in it.
+///
+/// # Safety
+///
+/// `path` must be a NUL terminated string, `arch` and `uuid` NULL or NUL
+/// terminated strings, and `error` NULL or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn atosl_open(
+    path: *const c_char,
+    arch: *const c_char,
+    uuid: *const c_char,
+    error: *mut *mut c_char,
+) -> *mut AtoslSymbolicator {
+    catch(error, || {
+        let path = optional_str(path)?.ok_or("path is NULL")?;
+        let symbolicator = Symbolicator::open(path, optional_str(arch)?, optional_str(uuid)?, &[])
+            .map_err(|err| err.to_string())?;
+        Ok(Box::into_raw(Box::new(AtoslSymbolicator { symbolicator })))
+    })
+}
+
+/// Opens an object or zipped dSYM archive held in memory; the data is
+/// copied. `name` is the module name results are reported under.
+///
+/// # Safety
+///
+/// `data` must be valid for reads of `len` bytes, `name` a NUL terminated
+/// string, `arch` and `uuid` NULL or NUL terminated strings, and `error`
+/// NULL or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn atosl_open_bytes(
+    data: *const u8,
+    len: usize,
+    name: *const c_char,
+    arch: *const c_char,
+    uuid: *const c_char,
+    error: *mut *mut c_char,
+) -> *mut AtoslSymbolicator {
+    catch(error, || {
+        if data.is_null() {
+            return Err("data is NULL".to_string());
+        }
+        let name = optional_str(name)?.ok_or("name is NULL")?;
+        let data = slice::from_raw_parts(data, len).to_vec();
+        let symbolicator = Symbolicator::from_vec(data, name, optional_str(arch)?, optional_str(uuid)?)
+            .map_err(|err| err.to_string())?;
+        Ok(Box::into_raw(Box::new(AtoslSymbolicator { symbolicator })))
+    })
+}
+
+/// Symbolicates `len` addresses, like `atosl.parse`: with `text_segment`
+/// every address is rebased from `load_address`, where the image was loaded,
+/// onto the text segment of the object, otherwise addresses are looked up as
+/// they are. Returns NULL on failure, see `atosl_open`.
+///
+/// # Safety
+///
+/// `symbolicator` must come from `atosl_open` or `atosl_open_bytes` and not
+/// be closed, `addresses` must be valid for reads of `len` addresses, and
+/// `error` NULL or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn atosl_symbolicate(
+    symbolicator: *const AtoslSymbolicator,
+    load_address: u64,
+    addresses: *const u64,
+    len: usize,
+    text_segment: bool,
+    error: *mut *mut c_char,
+) -> *mut AtoslResults {
+    catch(error, || {
+        let symbolicator = match symbolicator.as_ref() {
+            Some(symbolicator) => &symbolicator.symbolicator,
+            None => return Err("symbolicator is NULL".to_string()),
+        };
+        let addresses = if len == 0 {
+            Vec::new()
+        } else if addresses.is_null() {
+            return Err("addresses is NULL".to_string());
+        } else {
+            slice::from_raw_parts(addresses, len).to_vec()
+        };
+        let results = symbolicator
+            .symbolicate(load_address, addresses, text_segment)
+            .map_err(|err| err.to_string())?;
+        Ok(c_results(results))
+    })
+}
+
+/// Releases results and every string and frame they point to.
+///
+/// # Safety
+///
+/// `results` must be NULL or come from `atosl_symbolicate`, and is not
+/// usable afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn atosl_results_free(results: *mut AtoslResults) {
+    if !results.is_null() {
+        drop(Box::from_raw(results as *mut OwnedResults));
+    }
+}
+
+/// Closes an object, unmapping its file.
+///
+/// # Safety
+///
+/// `symbolicator` must be NULL or come from `atosl_open` or
+/// `atosl_open_bytes`, and is not usable afterwards. Results of it stay
+/// valid.
+#[no_mangle]
+pub unsafe extern "C" fn atosl_close(symbolicator: *mut AtoslSymbolicator) {
+    if !symbolicator.is_null() {
+        drop(Box::from_raw(symbolicator));
+    }
+}
+
+/// Releases an error message.
+///
+/// # Safety
+///
+/// `string` must be NULL or a message stored by this library.
+#[no_mangle]
+pub unsafe extern "C" fn atosl_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // build.rs generates the header into OUT_DIR
+    #[test]
+    fn committed_header_is_up_to_date() {
+        let generated = include_str!(concat!(env!("OUT_DIR"), "/atosl.h"));
+        let committed = include_str!("../include/atosl.h");
+        assert!(
+            generated == committed,
+            "include/atosl.h is out of date, run `ATOSL_UPDATE_HEADER=1 cargo build --no-default-features --features capi`"
+        );
+    }
+}
@@ -0,0 +1,197 @@
+use crate::atosl::{self, parse_hex_address, GroupAddress};
+use crate::{dsym, macho};
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::path::Path;
+
+// a line of the "Binary Images:" section, e.g.
+// 0x100f30000 - 0x100f37fff MyApp arm64  <1fd0d1c8a9cb3e3b8f6b8e2d5a4b3c2d> /private/var/.../MyApp
+struct BinaryImage {
+    start: u64,
+    end: u64,
+    uuid: [u8; 16],
+}
+
+fn parse_binary_image(line: &str) -> Option<BinaryImage> {
+    let (start, rest) = line.trim().split_once('-')?;
+    let start = parse_hex_address(start.trim()).ok()?;
+    let rest = rest.trim_start();
+    let end_len = rest.find(char::is_whitespace)?;
+    let end = parse_hex_address(&rest[..end_len]).ok()?;
+    let rest = &rest[end_len..];
+    let uuid_start = rest.find('<')?;
+    let uuid_end = uuid_start + rest[uuid_start..].find('>')?;
+    let uuid = macho::parse_uuid(&rest[uuid_start + 1..uuid_end]).ok()?;
+    Some(BinaryImage { start, end, uuid })
+}
+
+// a backtrace line, e.g.
+// 0   MyApp                         	0x0000000100f35a2c 0x100f30000 + 23084
+// returns the length of the line up to the end of the address and the address
+fn parse_frame_line(line: &str) -> Option<(usize, u64)> {
+    let mut tokens = line.split_whitespace();
+    let index = tokens.next()?;
+    if !index.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let address_token = tokens.find(|token| token.starts_with("0x"))?;
+    let address = parse_hex_address(address_token).ok()?;
+    // tokens are subslices of `line`
+    let address_end = address_token.as_ptr() as usize - line.as_ptr() as usize + address_token.len();
+    Some((address_end, address))
+}
+
+/// Symbolicates every backtrace frame of an Apple `.crash` text report whose
+/// binary image has a matching dSYM below `dsym_dir`, and returns the report
+/// with those frames rewritten. Frames of images without a dSYM, or whose
+/// dSYM fails to load, are kept as they are.
+pub fn symbolicate_crash_report(report: &str, dsym_dir: &str) -> Result<String> {
+    let mut images: Vec<BinaryImage> = Vec::new();
+    let mut in_binary_images = false;
+    for line in report.lines() {
+        if line.trim_start().starts_with("Binary Images:") {
+            in_binary_images = true;
+            continue;
+        }
+        if in_binary_images {
+            match parse_binary_image(line) {
+                Some(image) => images.push(image),
+                None if line.trim().is_empty() => in_binary_images = false,
+                None => {}
+            }
+        }
+    }
+    if images.is_empty() {
+        return Err(anyhow!("no binary images found in crash report"));
+    }
+
+    // frame addresses grouped by the image they belong to
+    let mut image_addresses: Vec<Vec<u64>> = images.iter().map(|_| Vec::new()).collect();
+    for line in report.lines() {
+        if let Some((_, address)) = parse_frame_line(line) {
+            if let Some(i) = images.iter().position(|image| address >= image.start && address <= image.end) {
+                if !image_addresses[i].contains(&address) {
+                    image_addresses[i].push(address);
+                }
+            }
+        }
+    }
+
+    let dsyms = dsym::index_uuids(Path::new(dsym_dir))?;
+    let mut symbolicated: HashMap<u64, String> = HashMap::new();
+    for (image, addresses) in images.iter().zip(image_addresses) {
+        if addresses.is_empty() {
+            continue;
+        }
+        let dsym_path = match dsyms.get(&image.uuid) {
+            Some(path) => path,
+            None => continue,
+        };
+        let uuid = macho::format_uuid(&image.uuid);
+        let results = match atosl::parse_file_addresses(
+            &dsym_path.to_string_lossy(),
+            None,
+            Some(&uuid),
+            &[],
+            vec![GroupAddress {
+                load_address: image.start,
+                addresses,
+                uuid: None,
+            }],
+            // frame addresses are runtime addresses in the image loaded at
+            // `image.start`
+            true,
+        ) {
+            Ok(results) => results,
+            // a dSYM that cannot be used only leaves its image's frames as
+            // they are
+            Err(_) => continue,
+        };
+        // unresolved addresses keep their original line
+        for result in results.into_iter().filter(|result| result.error.is_none()) {
+            symbolicated.insert(result.address, result.result);
+        }
+    }
+
+    let mut output = String::with_capacity(report.len());
+    for line in report.lines() {
+        match parse_frame_line(line).and_then(|(end, address)| Some((end, symbolicated.get(&address)?))) {
+            Some((end, symbol)) => {
+                output.push_str(&line[..end]);
+                output.push(' ');
+                output.push_str(symbol);
+            }
+            None => output.push_str(line),
+        }
+        output.push('\n');
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::macho::tests::{fat, thin, ARM64};
+    use std::fs;
+
+    #[test]
+    fn parses_binary_images() {
+        let image = parse_binary_image(
+            "0x104000000 - 0x104003fff MyApp arm64  <11111111222233334444555555555555> /private/var/MyApp.app/MyApp",
+        )
+        .unwrap();
+        assert_eq!((image.start, image.end), (0x104000000, 0x104003fff));
+        assert_eq!(macho::format_uuid(&image.uuid), "11111111-2222-3333-4444-555555555555");
+        // dashed uuids as printed by some tools
+        let image = parse_binary_image(
+            "       0x1b4a7a000 -        0x1b4aadfff libsystem_kernel.dylib arm64e  <d5b5a3c8-f2a4-3b0e-9c1f-5d7a6b8c9d0e> /usr/lib/system/libsystem_kernel.dylib",
+        )
+        .unwrap();
+        assert_eq!(image.start, 0x1b4a7a000);
+    }
+
+    #[test]
+    fn rejects_other_lines_as_binary_images() {
+        assert!(parse_binary_image("Binary Images:").is_none());
+        assert!(parse_binary_image("").is_none());
+        assert!(parse_binary_image("0x104000000 - 0x104003fff MyApp arm64 /private/var/MyApp").is_none());
+        assert!(parse_binary_image("0x104000000 - 0x104003fff MyApp arm64  <xyz> /private/var/MyApp").is_none());
+    }
+
+    #[test]
+    fn parses_frame_lines() {
+        let line = "1   MyApp                         \t0x0000000104001158 0x104000000 + 4440";
+        let (address_end, address) = parse_frame_line(line).unwrap();
+        assert_eq!(address, 0x104001158);
+        assert_eq!(&line[..address_end], "1   MyApp                         \t0x0000000104001158");
+    }
+
+    #[test]
+    fn rejects_other_lines_as_frames() {
+        assert!(parse_frame_line("Thread 0 Crashed:").is_none());
+        assert!(parse_frame_line("    x0: 0x0000000000000000   x1: 0x0000000000000000").is_none());
+        assert!(parse_frame_line("").is_none());
+        assert!(parse_frame_line("1   MyApp   0xzz 0x104000000 + 4440").is_none());
+    }
+
+    #[test]
+    fn skips_images_whose_dsym_cannot_be_used() {
+        let dir = std::env::temp_dir().join(format!("atosl-crash-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // two slices with the image's uuid, the object cannot be picked
+        let slice = thin(ARM64, Some([0x11; 16]));
+        fs::write(dir.join("MyApp"), fat(&[slice.clone(), slice])).unwrap();
+        let report = concat!(
+            "Thread 0 Crashed:\n",
+            "0   MyApp   \t0x0000000104001158 0x104000000 + 4440\n",
+            "1   libfoo.dylib   \t0x0000000105000010 0x105000000 + 16\n",
+            "\n",
+            "Binary Images:\n",
+            "0x104000000 - 0x104003fff MyApp arm64  <11111111111111111111111111111111> /private/var/MyApp\n",
+            "0x105000000 - 0x105003fff libfoo.dylib arm64  <22222222222222222222222222222222> /usr/lib/libfoo.dylib\n",
+        );
+        let output = symbolicate_crash_report(report, dir.to_str().unwrap()).unwrap();
+        assert_eq!(output, report);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,219 @@
+use crate::macho;
+use anyhow::{anyhow, Result};
+use object::{BinaryFormat, Object};
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+const MACHO_MAGICS: &[[u8; 4]] = &[
+    [0xfe, 0xed, 0xfa, 0xce],
+    [0xce, 0xfa, 0xed, 0xfe],
+    [0xfe, 0xed, 0xfa, 0xcf],
+    [0xcf, 0xfa, 0xed, 0xfe],
+    [0xca, 0xfe, 0xba, 0xbe],
+    [0xca, 0xfe, 0xba, 0xbf],
+];
+
+// reads only the magic so that large unrelated files are not mapped
+pub fn has_magic(path: &Path, magics: &[[u8; 4]]) -> bool {
+    let mut magic = [0u8; 4];
+    match fs::File::open(path) {
+        Ok(mut file) => file.read_exact(&mut magic).is_ok() && magics.contains(&magic),
+        Err(_) => false,
+    }
+}
+
+pub fn is_macho_file(path: &Path) -> bool {
+    has_magic(path, MACHO_MAGICS)
+}
+
+pub fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            collect_files(&entry.path(), files)?;
+        } else if file_type.is_file() {
+            files.push(entry.path());
+        }
+    }
+    Ok(())
+}
+
+/// An object of a file, one per slice of a universal Mach-O.
+pub struct ObjectId {
+    // the Mach-O UUID or the ELF build-id
+    pub id: Vec<u8>,
+    pub macho: bool,
+    pub dwarf: bool,
+}
+
+/// The id of every object in a Mach-O (every slice of a universal one) or
+/// ELF file, leaving out objects without one.
+pub fn object_ids(data: &[u8]) -> Result<Vec<ObjectId>> {
+    let mut objects: Vec<&[u8]> = macho::fat_slices(data)?.into_iter().map(|(_, slice)| slice).collect();
+    if objects.is_empty() {
+        objects.push(data);
+    }
+    let mut ids = Vec::new();
+    for data in objects {
+        let object = object::File::parse(data)?;
+        let (id, macho) = match object.format() {
+            BinaryFormat::MachO => (object.mach_uuid()?.map(|uuid| uuid.to_vec()), true),
+            BinaryFormat::Elf => (object.build_id()?.map(<[u8]>::to_vec), false),
+            _ => (None, false),
+        };
+        if let Some(id) = id {
+            let dwarf = object.section_by_name(".debug_info").is_some();
+            ids.push(ObjectId { id, macho, dwarf });
+        }
+    }
+    Ok(ids)
+}
+
+// the Mach-O uuids and whether each carries DWARF, none for unreadable
+// files, which are skipped like malformed ones
+fn file_uuids(path: &Path) -> Vec<([u8; 16], bool)> {
+    let mmap = match fs::File::open(path).and_then(|file| unsafe { memmap::Mmap::map(&file) }) {
+        Ok(mmap) => mmap,
+        Err(_) => return Vec::new(),
+    };
+    object_ids(&mmap)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|object| object.macho)
+        .filter_map(|object| Some((object.id.as_slice().try_into().ok()?, object.dwarf)))
+        .collect()
+}
+
+/// Maps the UUID of every Mach-O object (and every slice of universal ones)
+/// found below `dir` to the file containing it. An object carrying DWARF is
+/// preferred over a stripped one with the same UUID, e.g. the dSYM over the
+/// app binary next to it.
+pub fn index_uuids(dir: &Path) -> Result<HashMap<[u8; 16], PathBuf>> {
+    let mut files = Vec::new();
+    collect_files(dir, &mut files)?;
+    files.sort();
+    let mut uuids: HashMap<[u8; 16], (PathBuf, bool)> = HashMap::new();
+    for path in files {
+        if !is_macho_file(&path) {
+            continue;
+        }
+        for (uuid, dwarf) in file_uuids(&path) {
+            match uuids.get(&uuid) {
+                Some((_, true)) => {}
+                Some(_) if !dwarf => {}
+                _ => {
+                    uuids.insert(uuid, (path.clone(), dwarf));
+                }
+            }
+        }
+    }
+    Ok(uuids.into_iter().map(|(uuid, (path, _))| (uuid, path)).collect())
+}
+
+// the DWARF objects of a .dSYM bundle, or of every .dSYM below `dir`
+fn collect_dwarf_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    collect_files(dir, &mut files)?;
+    files.retain(|path| {
+        path.parent()
+            .is_some_and(|parent| parent.ends_with("Contents/Resources/DWARF"))
+            && is_macho_file(path)
+    });
+    files.sort();
+    Ok(files)
+}
+
+/// The DWARF objects of a `.dSYM` bundle, or of every `.dSYM` below a
+/// directory, by uuid. Each object is read once, however many uuids are
+/// resolved against them.
+pub struct DsymIndex {
+    dir: PathBuf,
+    files: Vec<PathBuf>,
+    uuids: HashMap<[u8; 16], PathBuf>,
+}
+
+impl DsymIndex {
+    pub fn open(dir: &Path) -> Result<DsymIndex> {
+        let files = collect_dwarf_files(dir)?;
+        let mut uuids = HashMap::new();
+        for file in &files {
+            for (uuid, _) in file_uuids(file) {
+                uuids.entry(uuid).or_insert_with(|| file.clone());
+            }
+        }
+        Ok(DsymIndex {
+            dir: dir.to_path_buf(),
+            files,
+            uuids,
+        })
+    }
+
+    /// The DWARF object containing `uuid`, or the only one when no uuid is
+    /// given.
+    pub fn resolve(&self, uuid: Option<&str>) -> Result<PathBuf> {
+        match uuid {
+            Some(uuid) => {
+                let uuid = macho::parse_uuid(uuid)?;
+                self.uuids.get(&uuid).cloned().ok_or_else(|| {
+                    anyhow!(
+                        "no dSYM with uuid {} in {}",
+                        macho::format_uuid(&uuid),
+                        self.dir.display()
+                    )
+                })
+            }
+            None => match self.files.len() {
+                0 => Err(anyhow!("no dSYM found in {}", self.dir.display())),
+                1 => Ok(self.files[0].clone()),
+                _ => Err(anyhow!(
+                    "{} contains several dSYMs, specify uuid (available: {})",
+                    self.dir.display(),
+                    self.files
+                        .iter()
+                        .map(|file| file.file_name().unwrap_or_default().to_string_lossy())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )),
+            },
+        }
+    }
+}
+
+/// Resolves the `file` given by callers to an object file. Files are used as
+/// they are; a `.dSYM` bundle or a directory of dSYMs is searched for the
+/// DWARF object containing `uuid`, or for its only DWARF object when no uuid
+/// is given (see `DsymIndex`).
+pub fn resolve_object(path: &Path, uuid: Option<&str>) -> Result<PathBuf> {
+    if !path.is_dir() {
+        return Ok(path.to_path_buf());
+    }
+    DsymIndex::open(path)?.resolve(uuid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::macho::tests::{fat, thin, ARM64};
+
+    #[test]
+    fn lists_object_ids() {
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        for (name, dwarf) in [("libfixture-dwarf5.so", true), ("libfixture-nodebug.so", false)] {
+            let ids = object_ids(&fs::read(fixtures.join(name)).unwrap()).unwrap();
+            assert_eq!(ids.len(), 1);
+            assert_eq!(crate::elf::format_build_id(&ids[0].id), "cb96879276038671d25a64e0306b28317c7624ed");
+            assert!(!ids[0].macho);
+            assert_eq!(ids[0].dwarf, dwarf);
+        }
+
+        let data = fat(&[thin(ARM64, Some([1; 16])), thin(ARM64, None), thin(ARM64, Some([2; 16]))]);
+        let ids = object_ids(&data).unwrap();
+        let uuids: Vec<&[u8]> = ids.iter().map(|object| &object.id[..]).collect();
+        assert_eq!(uuids, vec![&[1; 16][..], &[2; 16][..]]);
+        assert!(ids.iter().all(|object| object.macho && !object.dwarf));
+    }
+}
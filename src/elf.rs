@@ -0,0 +1,164 @@
+use crate::dsym;
+use anyhow::{anyhow, Result};
+use object::read::elf::{Dyn, FileHeader};
+use object::{elf, Endianness, FileKind, Object};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+
+pub fn is_elf_file(path: &Path) -> bool {
+    dsym::has_magic(path, &[ELF_MAGIC])
+}
+
+/// Parses a GNU build-id as printed by tombstones and `readelf -n`.
+pub fn parse_build_id(build_id: &str) -> Result<Vec<u8>> {
+    if build_id.is_empty() || build_id.len() % 2 != 0 || !build_id.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(anyhow!("invalid build id {}", build_id));
+    }
+    let mut bytes = Vec::with_capacity(build_id.len() / 2);
+    for i in (0..build_id.len()).step_by(2) {
+        bytes.push(u8::from_str_radix(&build_id[i..i + 2], 16)?);
+    }
+    Ok(bytes)
+}
+
+pub fn format_build_id(build_id: &[u8]) -> String {
+    build_id.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// The content of `.note.gnu.build-id`, None for files without one.
+pub fn object_build_id(data: &[u8]) -> Result<Option<Vec<u8>>> {
+    Ok(object::File::parse(data)?.build_id()?.map(|build_id| build_id.to_vec()))
+}
+
+/// The DT_SONAME of a shared object, None for other objects and for
+/// separate debug files, whose `.dynamic` has no contents.
+pub fn object_soname(data: &[u8]) -> Result<Option<String>> {
+    match FileKind::parse(data)? {
+        FileKind::Elf32 => header_soname::<elf::FileHeader32<Endianness>>(data),
+        FileKind::Elf64 => header_soname::<elf::FileHeader64<Endianness>>(data),
+        _ => Ok(None),
+    }
+}
+
+fn header_soname<Elf: FileHeader<Endian = Endianness>>(data: &[u8]) -> Result<Option<String>> {
+    let header = Elf::parse(data)?;
+    let endian = header.endian()?;
+    let sections = header.sections(endian, data)?;
+    let (dynamic, strings_index) = match sections.dynamic(endian, data)? {
+        Some(dynamic) => dynamic,
+        None => return Ok(None),
+    };
+    for entry in dynamic {
+        if entry.tag32(endian) == Some(elf::DT_SONAME) {
+            let strings = sections.strings(endian, data, strings_index)?;
+            let name = entry.string(endian, strings)?;
+            return Ok(Some(String::from_utf8_lossy(name).into_owned()));
+        }
+    }
+    Ok(None)
+}
+
+/// The ELF files found below a directory, by GNU build-id and by file name.
+pub struct SharedObjects {
+    by_build_id: HashMap<Vec<u8>, PathBuf>,
+    by_name: HashMap<String, Vec<PathBuf>>,
+}
+
+impl SharedObjects {
+    pub fn index(dir: &Path) -> Result<SharedObjects> {
+        let mut files = Vec::new();
+        dsym::collect_files(dir, &mut files)?;
+        let mut by_build_id = HashMap::new();
+        let mut by_name: HashMap<String, Vec<PathBuf>> = HashMap::new();
+        for path in files {
+            if !is_elf_file(&path) {
+                continue;
+            }
+            // an unreadable file is still found by name, failing only the
+            // frames that use it
+            let build_id = fs::File::open(&path)
+                .ok()
+                .and_then(|file| unsafe { memmap::Mmap::map(&file) }.ok())
+                .and_then(|mmap| object_build_id(&mmap).ok().flatten());
+            if let Some(build_id) = build_id {
+                by_build_id.entry(build_id).or_insert_with(|| path.clone());
+            }
+            if let Some(name) = path.file_name().and_then(|name| name.to_str()) {
+                by_name.entry(name.to_string()).or_default().push(path.clone());
+            }
+        }
+        Ok(SharedObjects { by_build_id, by_name })
+    }
+
+    /// Finds the object a frame refers to. With a build-id only the object
+    /// carrying it matches, so a stale library of the same name is never
+    /// used; without one the first object named `name` is taken.
+    pub fn find(&self, name: &str, build_id: Option<&[u8]>) -> Option<&PathBuf> {
+        match build_id {
+            Some(build_id) => self.by_build_id.get(build_id),
+            None => self.by_name.get(name).and_then(|paths| paths.first()),
+        }
+    }
+}
+
+fn read_crc32(path: &Path) -> Result<u32> {
+    let file = fs::File::open(path)?;
+    let mmap = unsafe { memmap::Mmap::map(&file)? };
+    Ok(crc32fast::hash(&mmap))
+}
+
+/// Locates the separate debug file of a stripped ELF object the way gdb does:
+/// `<dir>/.build-id/xx/yyyy.debug` for each of `debug_dirs`, then the
+/// `.gnu_debuglink` name next to the object, in its `.debug` directory and
+/// below each of `debug_dirs`, accepted only when its CRC32 matches. None
+/// when the object is not ELF, already carries DWARF, or nothing is found.
+pub fn find_debug_file(object_path: &Path, debug_dirs: &[String]) -> Result<Option<PathBuf>> {
+    if !is_elf_file(object_path) {
+        return Ok(None);
+    }
+    let file = fs::File::open(object_path)?;
+    let mmap = unsafe { memmap::Mmap::map(&file)? };
+    let object = object::File::parse(&*mmap)?;
+    if object.section_by_name(".debug_info").is_some() {
+        return Ok(None);
+    }
+
+    if let Some(build_id) = object.build_id()? {
+        if build_id.len() > 1 {
+            let build_id = format_build_id(build_id);
+            for dir in debug_dirs {
+                let path = Path::new(dir)
+                    .join(".build-id")
+                    .join(&build_id[..2])
+                    .join(format!("{}.debug", &build_id[2..]));
+                if path.is_file() {
+                    return Ok(Some(path));
+                }
+            }
+        }
+    }
+
+    if let Some((name, crc)) = object.gnu_debuglink()? {
+        let name = std::str::from_utf8(name)?;
+        let object_dir = object_path.parent().unwrap_or_else(|| Path::new(""));
+        let mut candidates = vec![object_dir.join(name), object_dir.join(".debug").join(name)];
+        for dir in debug_dirs {
+            candidates.push(Path::new(dir).join(name));
+            // gdb also mirrors the absolute directory of the object
+            if let Ok(object_dir) = object_dir.canonicalize() {
+                if let Ok(relative) = object_dir.strip_prefix("/") {
+                    candidates.push(Path::new(dir).join(relative).join(name));
+                }
+            }
+        }
+        for path in candidates {
+            if path.is_file() && path != object_path && read_crc32(&path)? == crc {
+                return Ok(Some(path));
+            }
+        }
+    }
+    Ok(None)
+}
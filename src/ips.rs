@@ -0,0 +1,215 @@
+use crate::atosl::{self, GroupAddress, ResponseResult};
+use crate::{dsym, macho};
+use anyhow::{anyhow, Result};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::Path;
+
+// an entry of "usedImages"
+struct UsedImage {
+    base: u64,
+    uuid: Option<[u8; 16]>,
+}
+
+fn used_images(body: &Value) -> Result<Vec<UsedImage>> {
+    let images = body
+        .get("usedImages")
+        .and_then(Value::as_array)
+        .ok_or_else(|| anyhow!("no usedImages in ips report"))?;
+    Ok(images
+        .iter()
+        .map(|image| UsedImage {
+            base: image.get("base").and_then(Value::as_u64).unwrap_or(0),
+            uuid: image
+                .get("uuid")
+                .and_then(Value::as_str)
+                .and_then(|uuid| macho::parse_uuid(uuid).ok()),
+        })
+        .collect())
+}
+
+// every frame list of the report: each thread's "frames" and
+// "lastExceptionBacktrace"
+fn frame_lists(body: &mut Value) -> Vec<&mut Vec<Value>> {
+    let mut lists = Vec::new();
+    let body = match body.as_object_mut() {
+        Some(body) => body,
+        None => return lists,
+    };
+    for (key, value) in body.iter_mut() {
+        match (key.as_str(), value) {
+            ("threads", Value::Array(threads)) => {
+                for thread in threads {
+                    if let Some(Value::Array(frames)) = thread.get_mut("frames") {
+                        lists.push(frames);
+                    }
+                }
+            }
+            ("lastExceptionBacktrace", Value::Array(frames)) => lists.push(frames),
+            _ => {}
+        }
+    }
+    lists
+}
+
+fn frame_image(frame: &Value) -> Option<(usize, u64)> {
+    let image_index = frame.get("imageIndex")?.as_u64()? as usize;
+    let image_offset = frame.get("imageOffset")?.as_u64()?;
+    Some((image_index, image_offset))
+}
+
+// the image index and absolute address of a frame, None for frames of
+// unknown images or whose offset overflows the image base
+fn frame_address(frame: &Value, images: &[UsedImage]) -> Option<(usize, u64)> {
+    let (image_index, image_offset) = frame_image(frame)?;
+    let address = images.get(image_index)?.base.checked_add(image_offset)?;
+    Some((image_index, address))
+}
+
+/// Symbolicates an iOS 15+ `.ips` crash report: every thread frame (and the
+/// last exception backtrace) whose image has a matching dSYM below `dsym_dir`
+/// gets `symbol`, `symbolLocation`, `sourceFile` and `sourceLine` filled in;
+/// images whose dSYM fails to load are left alone.
+/// Returns the report in the same two-document layout, metadata line first.
+pub fn symbolicate_ips_report(report: &str, dsym_dir: &str) -> Result<String> {
+    let report = report.trim_start();
+    let (header, body) = match report.find('\n') {
+        Some(index) => report.split_at(index),
+        None => return Err(anyhow!("ips report has no body")),
+    };
+    let header: Value = serde_json::from_str(header)?;
+    let mut body: Value = serde_json::from_str(body)?;
+
+    let images = used_images(&body)?;
+    let mut image_addresses: Vec<Vec<u64>> = images.iter().map(|_| Vec::new()).collect();
+    for frames in frame_lists(&mut body) {
+        for frame in frames.iter() {
+            if let Some((image_index, address)) = frame_address(frame, &images) {
+                if !image_addresses[image_index].contains(&address) {
+                    image_addresses[image_index].push(address);
+                }
+            }
+        }
+    }
+
+    let dsyms = dsym::index_uuids(Path::new(dsym_dir))?;
+    let mut symbolicated: HashMap<(usize, u64), ResponseResult> = HashMap::new();
+    for (image_index, (image, addresses)) in images.iter().zip(image_addresses).enumerate() {
+        if addresses.is_empty() {
+            continue;
+        }
+        let (dsym_path, uuid) = match image.uuid.and_then(|uuid| Some((dsyms.get(&uuid)?, uuid))) {
+            Some(found) => found,
+            None => continue,
+        };
+        let results = match atosl::parse_file_addresses(
+            &dsym_path.to_string_lossy(),
+            None,
+            Some(&macho::format_uuid(&uuid)),
+            &[],
+            vec![GroupAddress {
+                load_address: image.base,
+                addresses,
+                uuid: None,
+            }],
+            true,
+        ) {
+            Ok(results) => results,
+            // the frames of this image stay unsymbolicated
+            Err(_) => continue,
+        };
+        for result in results {
+            symbolicated.insert((image_index, result.address), result);
+        }
+    }
+
+    for frames in frame_lists(&mut body) {
+        for frame in frames.iter_mut() {
+            let symbolicated_frame = match frame_address(frame, &images)
+                .and_then(|address| symbolicated.get(&address)?.frame.as_ref())
+            {
+                Some(symbolicated_frame) => symbolicated_frame,
+                None => continue,
+            };
+            let frame = match frame.as_object_mut() {
+                Some(frame) => frame,
+                None => continue,
+            };
+            frame.insert("symbol".to_string(), json!(symbolicated_frame.demangled_symbol));
+            frame.insert("symbolLocation".to_string(), json!(symbolicated_frame.symbol_offset));
+            if let (Some(file), Some(line)) = (&symbolicated_frame.file, symbolicated_frame.line) {
+                frame.insert("sourceFile".to_string(), json!(file));
+                frame.insert("sourceLine".to_string(), json!(line));
+            }
+        }
+    }
+
+    Ok(format!(
+        "{}\n{}\n",
+        serde_json::to_string(&header)?,
+        serde_json::to_string_pretty(&body)?
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::macho::tests::{fat, thin, ARM64};
+    use std::fs;
+
+    #[test]
+    fn reads_used_images() {
+        let body = json!({"usedImages": [
+            {"base": 4362076160u64, "uuid": "11111111-2222-3333-4444-555555555555", "name": "MyApp"},
+            {"base": 7323754496u64},
+            {"uuid": "not a uuid"},
+        ]});
+        let images = used_images(&body).unwrap();
+        assert_eq!(images.len(), 3);
+        assert_eq!(images[0].base, 0x104000000);
+        assert_eq!(
+            images[0].uuid.map(|uuid| macho::format_uuid(&uuid)).as_deref(),
+            Some("11111111-2222-3333-4444-555555555555")
+        );
+        assert!(images[1].uuid.is_none());
+        assert_eq!(images[2].base, 0);
+        assert!(images[2].uuid.is_none());
+        assert!(used_images(&json!({})).is_err());
+    }
+
+    #[test]
+    fn reads_frame_images() {
+        assert_eq!(frame_image(&json!({"imageOffset": 4440, "imageIndex": 1})), Some((1, 4440)));
+        assert_eq!(frame_image(&json!({"imageOffset": 4440})), None);
+        assert_eq!(frame_image(&json!({"imageOffset": -1, "imageIndex": 1})), None);
+    }
+
+    #[test]
+    fn skips_frames_outside_the_images() {
+        let images = vec![UsedImage {
+            base: u64::MAX - 1,
+            uuid: None,
+        }];
+        assert_eq!(frame_address(&json!({"imageOffset": 1, "imageIndex": 0}), &images), Some((0, u64::MAX)));
+        assert_eq!(frame_address(&json!({"imageOffset": 2, "imageIndex": 0}), &images), None);
+        assert_eq!(frame_address(&json!({"imageOffset": 1, "imageIndex": 1}), &images), None);
+    }
+
+    #[test]
+    fn skips_images_whose_dsym_cannot_be_used() {
+        let dir = std::env::temp_dir().join(format!("atosl-ips-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // two slices with the image's uuid, the object cannot be picked
+        let slice = thin(ARM64, Some([0x11; 16]));
+        fs::write(dir.join("MyApp"), fat(&[slice.clone(), slice])).unwrap();
+        let body = json!({
+            "usedImages": [{"base": 4362076160u64, "uuid": "11111111-1111-1111-1111-111111111111"}],
+            "threads": [{"frames": [{"imageOffset": 4440, "imageIndex": 0}]}],
+        });
+        let report = format!("{}\n{}", json!({"bug_type": "309"}), body);
+        let output = symbolicate_ips_report(&report, dir.to_str().unwrap()).unwrap();
+        let (_, output_body) = output.split_once('\n').unwrap();
+        assert_eq!(serde_json::from_str::<Value>(output_body).unwrap(), body);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,348 @@
+use anyhow::{anyhow, Result};
+use object::macho;
+use object::read::macho::{FatArch, FatHeader, LoadCommandVariant, MachHeader};
+use object::{Endianness, FileKind, Object};
+
+// (name, cputype, cpusubtype) as used by `atos -arch`
+const ARCHS: &[(&str, u32, u32)] = &[
+    ("i386", macho::CPU_TYPE_X86, macho::CPU_SUBTYPE_I386_ALL),
+    ("x86_64", macho::CPU_TYPE_X86_64, macho::CPU_SUBTYPE_X86_64_ALL),
+    ("x86_64h", macho::CPU_TYPE_X86_64, macho::CPU_SUBTYPE_X86_64_H),
+    ("armv6", macho::CPU_TYPE_ARM, macho::CPU_SUBTYPE_ARM_V6),
+    ("armv7", macho::CPU_TYPE_ARM, macho::CPU_SUBTYPE_ARM_V7),
+    ("armv7s", macho::CPU_TYPE_ARM, macho::CPU_SUBTYPE_ARM_V7S),
+    ("armv7k", macho::CPU_TYPE_ARM, macho::CPU_SUBTYPE_ARM_V7K),
+    ("arm64", macho::CPU_TYPE_ARM64, macho::CPU_SUBTYPE_ARM64_ALL),
+    ("arm64e", macho::CPU_TYPE_ARM64, macho::CPU_SUBTYPE_ARM64E),
+    ("arm64_32", macho::CPU_TYPE_ARM64_32, macho::CPU_SUBTYPE_ARM64_32_V8),
+];
+
+pub fn arch_name(cputype: u32, cpusubtype: u32) -> String {
+    let cpusubtype = cpusubtype & !macho::CPU_SUBTYPE_MASK;
+    ARCHS
+        .iter()
+        .find(|(_, t, s)| *t == cputype && *s == cpusubtype)
+        .map(|(name, _, _)| name.to_string())
+        .unwrap_or_else(|| format!("cputype {} cpusubtype {}", cputype, cpusubtype))
+}
+
+fn is_known_arch(arch: &str) -> bool {
+    ARCHS.iter().any(|(name, _, _)| *name == arch)
+}
+
+// (cputype, cpusubtype) of a thin Mach-O, None for anything else
+fn thin_arch(data: &[u8]) -> Result<Option<(u32, u32)>> {
+    match FileKind::parse(data)? {
+        FileKind::MachO32 => {
+            let header = macho::MachHeader32::<Endianness>::parse(data, 0)?;
+            let endian = header.endian()?;
+            Ok(Some((header.cputype(endian), header.cpusubtype(endian))))
+        }
+        FileKind::MachO64 => {
+            let header = macho::MachHeader64::<Endianness>::parse(data, 0)?;
+            let endian = header.endian()?;
+            Ok(Some((header.cputype(endian), header.cpusubtype(endian))))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// The slices of a universal binary as (arch name, slice data). Empty if
+/// `data` is not a universal binary.
+pub fn fat_slices(data: &[u8]) -> Result<Vec<(String, &[u8])>> {
+    let mut slices = Vec::new();
+    match FileKind::parse(data)? {
+        FileKind::MachOFat32 => {
+            for arch in FatHeader::parse_arch32(data)? {
+                slices.push((arch_name(arch.cputype(), arch.cpusubtype()), arch.data(data)?));
+            }
+        }
+        FileKind::MachOFat64 => {
+            for arch in FatHeader::parse_arch64(data)? {
+                slices.push((arch_name(arch.cputype(), arch.cpusubtype()), arch.data(data)?));
+            }
+        }
+        _ => {}
+    }
+    Ok(slices)
+}
+
+/// Parses a Mach-O UUID as printed by crash reports and `dwarfdump --uuid`,
+/// with or without dashes.
+pub fn parse_uuid(uuid: &str) -> Result<[u8; 16]> {
+    let hex = uuid.replace('-', "");
+    if hex.len() != 32 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(anyhow!("invalid uuid {}", uuid));
+    }
+    let mut bytes = [0u8; 16];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)?;
+    }
+    Ok(bytes)
+}
+
+pub fn format_uuid(uuid: &[u8; 16]) -> String {
+    let hex = uuid.iter().map(|b| format!("{:02X}", b)).collect::<String>();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+pub fn object_uuid(data: &[u8]) -> Result<Option<[u8; 16]>> {
+    Ok(object::File::parse(data)?.mach_uuid()?)
+}
+
+/// The install name (LC_ID_DYLIB) of a thin Mach-O dylib, None for other
+/// objects.
+pub fn install_name(data: &[u8]) -> Result<Option<String>> {
+    match FileKind::parse(data)? {
+        FileKind::MachO32 => header_install_name::<macho::MachHeader32<Endianness>>(data),
+        FileKind::MachO64 => header_install_name::<macho::MachHeader64<Endianness>>(data),
+        _ => Ok(None),
+    }
+}
+
+fn header_install_name<Mach: MachHeader<Endian = Endianness>>(data: &[u8]) -> Result<Option<String>> {
+    let header = Mach::parse(data, 0)?;
+    let endian = header.endian()?;
+    let mut commands = header.load_commands(endian, data, 0)?;
+    while let Some(command) = commands.next()? {
+        if let LoadCommandVariant::IdDylib(dylib) = command.variant()? {
+            let name = command.string(endian, dylib.dylib.name)?;
+            return Ok(Some(String::from_utf8_lossy(name).into_owned()));
+        }
+    }
+    Ok(None)
+}
+
+/// Picks the object to symbolicate from `data`. Universal binaries need
+/// `arch` or `uuid` unless they contain a single slice; the chosen object is
+/// checked against both when they are given.
+pub fn select_slice<'data>(
+    data: &'data [u8],
+    arch: Option<&str>,
+    uuid: Option<&str>,
+) -> Result<&'data [u8]> {
+    if let Some(arch) = arch {
+        if !is_known_arch(arch) {
+            return Err(anyhow!("unknown architecture {}", arch));
+        }
+    }
+    let uuid = match uuid {
+        Some(uuid) => Some(parse_uuid(uuid)?),
+        None => None,
+    };
+    let slices = fat_slices(data)?;
+    if slices.is_empty() {
+        if let (Some(arch), Some((cputype, cpusubtype))) = (arch, thin_arch(data)?) {
+            let file_arch = arch_name(cputype, cpusubtype);
+            if file_arch != arch {
+                return Err(anyhow!("file is {}, not {}", file_arch, arch));
+            }
+        }
+        if let Some(uuid) = uuid {
+            match object_uuid(data)? {
+                Some(file_uuid) if file_uuid == uuid => {}
+                Some(file_uuid) => {
+                    return Err(anyhow!(
+                        "uuid mismatch: expected {}, file has {}",
+                        format_uuid(&uuid),
+                        format_uuid(&file_uuid)
+                    ))
+                }
+                None => return Err(anyhow!("file has no uuid, expected {}", format_uuid(&uuid))),
+            }
+        }
+        return Ok(data);
+    }
+    let mut available = Vec::new();
+    let mut candidates = Vec::new();
+    for (name, slice) in slices {
+        let slice_uuid = object_uuid(slice)?;
+        available.push(match slice_uuid {
+            Some(ref slice_uuid) => format!("{} {}", name, format_uuid(slice_uuid)),
+            None => name.clone(),
+        });
+        let arch_matches = arch.is_none_or(|arch| arch == name);
+        let uuid_matches = uuid.is_none_or(|uuid| slice_uuid == Some(uuid));
+        if arch_matches && uuid_matches {
+            candidates.push(slice);
+        }
+    }
+    let available = available.join(", ");
+    match (candidates.len(), arch, uuid) {
+        (1, _, _) => Ok(candidates[0]),
+        (0, _, Some(uuid)) => Err(anyhow!(
+            "no slice with uuid {} in universal file (available: {})",
+            format_uuid(&uuid),
+            available
+        )),
+        (0, Some(arch), None) => Err(anyhow!(
+            "no {} slice in universal file (available: {})",
+            arch,
+            available
+        )),
+        _ => Err(anyhow!(
+            "universal file contains several architectures, specify arch or uuid (available: {})",
+            available
+        )),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) const ARM64: (u32, u32) = (macho::CPU_TYPE_ARM64, macho::CPU_SUBTYPE_ARM64_ALL);
+
+    // a little endian 64-bit Mach-O header of `arch` with a dummy load
+    // command (LC_SOURCE_VERSION) and, when given, an LC_UUID
+    pub(crate) fn thin((cputype, cpusubtype): (u32, u32), uuid: Option<[u8; 16]>) -> Vec<u8> {
+        let mut commands = Vec::new();
+        commands.extend_from_slice(&macho::LC_SOURCE_VERSION.to_le_bytes());
+        commands.extend_from_slice(&16u32.to_le_bytes());
+        commands.extend_from_slice(&[0; 8]);
+        if let Some(uuid) = uuid {
+            commands.extend_from_slice(&macho::LC_UUID.to_le_bytes());
+            commands.extend_from_slice(&24u32.to_le_bytes());
+            commands.extend_from_slice(&uuid);
+        }
+        let ncmds: u32 = if uuid.is_some() { 2 } else { 1 };
+        let mut data = vec![0xcf, 0xfa, 0xed, 0xfe];
+        data.extend_from_slice(&cputype.to_le_bytes());
+        data.extend_from_slice(&cpusubtype.to_le_bytes());
+        data.extend_from_slice(&macho::MH_DYLIB.to_le_bytes());
+        data.extend_from_slice(&ncmds.to_le_bytes());
+        data.extend_from_slice(&(commands.len() as u32).to_le_bytes());
+        // flags, reserved
+        data.extend_from_slice(&[0; 8]);
+        data.extend_from_slice(&commands);
+        // section data that must not be needed
+        data.extend_from_slice(&[0xab; 64]);
+        data
+    }
+
+    // a universal file of `slices`, laid out in reverse order, with the
+    // arch each slice's header declares
+    pub(crate) fn fat(slices: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = vec![0xca, 0xfe, 0xba, 0xbe];
+        data.extend_from_slice(&(slices.len() as u32).to_be_bytes());
+        let mut offset = 8 + 20 * slices.len();
+        let mut offsets = Vec::new();
+        for slice in slices.iter().rev() {
+            offsets.push(offset);
+            offset += slice.len();
+        }
+        offsets.reverse();
+        for (slice, offset) in slices.iter().zip(&offsets) {
+            // cputype and cpusubtype, from little to big endian
+            for field in slice[4..12].chunks(4) {
+                data.extend(field.iter().rev());
+            }
+            data.extend_from_slice(&(*offset as u32).to_be_bytes());
+            data.extend_from_slice(&(slice.len() as u32).to_be_bytes());
+            // align
+            data.extend_from_slice(&[0; 4]);
+        }
+        for slice in slices.iter().rev() {
+            data.extend_from_slice(slice);
+        }
+        data
+    }
+
+    const X86_64: (u32, u32) = (macho::CPU_TYPE_X86_64, macho::CPU_SUBTYPE_X86_64_ALL);
+
+    fn error(result: Result<&[u8]>) -> String {
+        result.err().unwrap().to_string()
+    }
+
+    #[test]
+    fn lists_universal_slices() {
+        let arm64 = thin(ARM64, None);
+        let x86_64 = thin(X86_64, None);
+        let data = fat(&[arm64.clone(), x86_64.clone()]);
+        let slices = fat_slices(&data).unwrap();
+        assert_eq!(slices, vec![("arm64".to_string(), &arm64[..]), ("x86_64".to_string(), &x86_64[..])]);
+        assert!(fat_slices(&arm64).unwrap().is_empty());
+    }
+
+    #[test]
+    fn selects_slices_by_arch() {
+        let arm64 = thin(ARM64, None);
+        let data = fat(&[thin(X86_64, None), arm64.clone()]);
+        assert_eq!(select_slice(&data, Some("arm64"), None).unwrap(), &arm64[..]);
+        assert_eq!(
+            error(select_slice(&data, None, None)),
+            "universal file contains several architectures, specify arch or uuid (available: x86_64, arm64)"
+        );
+        assert_eq!(
+            error(select_slice(&data, Some("armv7"), None)),
+            "no armv7 slice in universal file (available: x86_64, arm64)"
+        );
+        assert_eq!(error(select_slice(&data, Some("sparc"), None)), "unknown architecture sparc");
+        // a single slice needs no arch
+        let single = fat(std::slice::from_ref(&arm64));
+        assert_eq!(select_slice(&single, None, None).unwrap(), &arm64[..]);
+    }
+
+    #[test]
+    fn checks_the_arch_of_thin_files() {
+        let data = thin(ARM64, None);
+        assert_eq!(select_slice(&data, Some("arm64"), None).unwrap(), &data[..]);
+        assert_eq!(select_slice(&data, None, None).unwrap(), &data[..]);
+        assert_eq!(error(select_slice(&data, Some("x86_64"), None)), "file is arm64, not x86_64");
+    }
+
+    #[test]
+    fn parses_uuids_with_or_without_dashes() {
+        let bytes = [
+            0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef, 0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef,
+        ];
+        assert_eq!(parse_uuid("01234567-89AB-CDEF-0123-456789ABCDEF").unwrap(), bytes);
+        assert_eq!(parse_uuid("0123456789abcdef0123456789abcdef").unwrap(), bytes);
+        assert_eq!(format_uuid(&bytes), "01234567-89AB-CDEF-0123-456789ABCDEF");
+        for uuid in ["", "0123456789abcdef", "0123456789abcdef0123456789abcdeg", "0123456789abcdef0123456789abcdef00"] {
+            assert_eq!(parse_uuid(uuid).err().unwrap().to_string(), format!("invalid uuid {}", uuid));
+        }
+    }
+
+    #[test]
+    fn selects_slices_by_uuid() {
+        let arm64 = thin(ARM64, Some([1; 16]));
+        let data = fat(&[thin(X86_64, Some([2; 16])), arm64.clone(), thin(ARM64, None)]);
+        let uuid = format_uuid(&[1; 16]);
+        assert_eq!(select_slice(&data, None, Some(&uuid)).unwrap(), &arm64[..]);
+        assert_eq!(select_slice(&data, Some("arm64"), Some(&uuid)).unwrap(), &arm64[..]);
+        // the available list names the uuid of each slice that has one
+        let available = format!("x86_64 {}, arm64 {}, arm64", format_uuid(&[2; 16]), uuid);
+        assert_eq!(
+            error(select_slice(&data, None, Some(&format_uuid(&[3; 16])))),
+            format!("no slice with uuid {} in universal file (available: {})", format_uuid(&[3; 16]), available)
+        );
+        assert_eq!(
+            error(select_slice(&data, Some("x86_64"), Some(&uuid))),
+            format!("no slice with uuid {} in universal file (available: {})", uuid, available)
+        );
+    }
+
+    #[test]
+    fn checks_the_uuid_of_thin_files() {
+        let uuid = format_uuid(&[1; 16]);
+        let data = thin(ARM64, Some([1; 16]));
+        assert_eq!(select_slice(&data, None, Some(&uuid)).unwrap(), &data[..]);
+        assert_eq!(
+            error(select_slice(&thin(ARM64, Some([2; 16])), None, Some(&uuid))),
+            format!("uuid mismatch: expected {}, file has {}", uuid, format_uuid(&[2; 16]))
+        );
+        assert_eq!(
+            error(select_slice(&thin(ARM64, None), None, Some(&uuid))),
+            format!("file has no uuid, expected {}", uuid)
+        );
+        assert_eq!(error(select_slice(&data, None, Some("not-a-uuid"))), "invalid uuid not-a-uuid");
+    }
+}
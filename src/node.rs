@@ -0,0 +1,743 @@
+use crate::atosl::{self, parse_address_string, Frame, GroupAddress, Lookup, ObjectInput, ResponseResult, Symbolicator};
+use crate::remote::{ServerLayout, SymbolServer};
+use crate::store::SymbolStore;
+use crate::{crash, ips, tombstone};
+use neon::prelude::*;
+use neon::types::buffer::TypedArray;
+use std::cell::RefCell;
+
+type BoxedSymbolicator = JsBox<(RefCell<Option<Symbolicator<'static>>>, AddressFormat)>;
+
+type BoxedStore = JsBox<(SymbolStore, AddressFormat)>;
+
+impl Finalize for Symbolicator<'static> {}
+
+impl Finalize for SymbolStore {}
+
+impl Finalize for AddressFormat {}
+
+// how addresses are returned: numbers cannot hold addresses above 2^53 - 1,
+// e.g. arm64e pointers and kernel addresses, which are returned as hex
+// strings instead
+#[derive(Clone, Copy)]
+enum AddressFormat {
+    Number,
+    BigInt,
+    Hex,
+}
+
+// numbers above this are not exact
+const MAX_SAFE_INTEGER: f64 = 9007199254740991.0;
+
+// `key` as it appears in error messages, e.g. `addresses[2].load_address`
+fn field_name(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", prefix, key)
+    }
+}
+
+// downcasts an argument, throwing a TypeError naming `field` and the
+// `expected` type instead of neon's generic downcast error
+fn downcast_field<'a, V: Value>(
+    cx: &mut FunctionContext<'a>,
+    value: Handle<'a, JsValue>,
+    field: &str,
+    expected: &str,
+) -> NeonResult<Handle<'a, V>> {
+    match value.downcast::<V, _>(cx) {
+        Ok(value) => Ok(value),
+        Err(_) => cx.throw_type_error(format!("{} must be {}", field, expected)),
+    }
+}
+
+fn required_field<'a, V: Value>(
+    cx: &mut FunctionContext<'a>,
+    obj: Handle<JsObject>,
+    prefix: &str,
+    key: &str,
+    expected: &str,
+) -> NeonResult<Handle<'a, V>> {
+    let field = field_name(prefix, key);
+    let value = obj.get_value(cx, key)?;
+    if value.is_a::<JsUndefined, _>(cx) || value.is_a::<JsNull, _>(cx) {
+        return cx.throw_type_error(format!("{} is required", field));
+    }
+    downcast_field(cx, value, &field, expected)
+}
+
+fn optional_field<'a, V: Value>(
+    cx: &mut FunctionContext<'a>,
+    obj: Handle<JsObject>,
+    prefix: &str,
+    key: &str,
+    expected: &str,
+) -> NeonResult<Option<Handle<'a, V>>> {
+    let value = obj.get_value(cx, key)?;
+    if value.is_a::<JsUndefined, _>(cx) || value.is_a::<JsNull, _>(cx) {
+        return Ok(None);
+    }
+    downcast_field(cx, value, &field_name(prefix, key), expected).map(Some)
+}
+
+fn object_argument<'a>(cx: &mut FunctionContext<'a>, i: i32, name: &str) -> JsResult<'a, JsObject> {
+    match cx.argument_opt(i) {
+        Some(value) => downcast_field(cx, value, name, "an object"),
+        None => cx.throw_type_error(format!("{} is required", name)),
+    }
+}
+
+fn string_argument(cx: &mut FunctionContext, i: i32, name: &str) -> NeonResult<String> {
+    match cx.argument_opt(i) {
+        Some(value) => Ok(downcast_field::<JsString>(cx, value, name, "a string")?.value(cx)),
+        None => cx.throw_type_error(format!("{} is required", name)),
+    }
+}
+
+// the hex digits of a BigInt, None when `value` is not one
+fn bigint_hex<'a>(cx: &mut FunctionContext<'a>, value: Handle<'a, JsValue>) -> NeonResult<Option<String>> {
+    let bigint: Handle<JsFunction> = cx.global().get(cx, "BigInt")?;
+    let prototype: Handle<JsObject> = bigint.get(cx, "prototype")?;
+    let to_string: Handle<JsFunction> = prototype.get(cx, "toString")?;
+    // BigInt.prototype.toString throws for anything but a BigInt
+    let hex = cx.try_catch(|cx| {
+        let radix = cx.number(16);
+        to_string.call(cx, value, [radix.upcast::<JsValue>()])
+    });
+    match hex {
+        Ok(hex) => Ok(Some(hex.downcast_or_throw::<JsString, _>(cx)?.value(cx))),
+        Err(_) => Ok(None),
+    }
+}
+
+// an address given as a decimal or 0x-prefixed string, a number or a BigInt
+fn address_value<'a>(cx: &mut FunctionContext<'a>, value: Handle<'a, JsValue>, field: &str) -> NeonResult<u64> {
+    if let Ok(number) = value.downcast::<JsNumber, _>(cx) {
+        let number = number.value(cx);
+        if !(0.0..=MAX_SAFE_INTEGER).contains(&number) || number.fract() != 0.0 {
+            return cx.throw_type_error(format!(
+                "{} is not a valid address ({}): numbers must be integers between 0 and 2^53 - 1, pass larger addresses as a BigInt or string",
+                field, number
+            ));
+        }
+        return Ok(number as u64);
+    }
+    let address = match value.downcast::<JsString, _>(cx) {
+        Ok(address) => address.value(cx),
+        Err(_) => match bigint_hex(cx, value)? {
+            Some(hex) if hex.starts_with('-') => {
+                return cx.throw_type_error(format!("{} is not a valid address (-0x{}n): negative", field, &hex[1..]))
+            }
+            Some(hex) => match u64::from_str_radix(&hex, 16) {
+                Ok(address) => return Ok(address),
+                Err(err) => return cx.throw_type_error(format!("{} is not a valid address (0x{}n): {}", field, hex, err)),
+            },
+            None => return cx.throw_type_error(format!("{} must be an address string, number or BigInt", field)),
+        },
+    };
+    match parse_address_string(&address) {
+        Ok(address) => Ok(address),
+        Err(err) => cx.throw_type_error(format!("{} is not a valid address ({:?}): {}", field, address, err)),
+    }
+}
+
+fn address_format_option(cx: &mut FunctionContext, obj: Handle<JsObject>) -> NeonResult<AddressFormat> {
+    match optional_string(cx, obj, "address_format")?.as_deref() {
+        None | Some("number") => Ok(AddressFormat::Number),
+        Some("bigint") => Ok(AddressFormat::BigInt),
+        Some("hex") => Ok(AddressFormat::Hex),
+        Some(format) => cx.throw_type_error(format!(
+            "address_format must be 'number', 'bigint' or 'hex', got {:?}",
+            format
+        )),
+    }
+}
+
+fn address_output<'a, C: Context<'a>>(cx: &mut C, format: AddressFormat, address: u64) -> JsResult<'a, JsValue> {
+    match format {
+        AddressFormat::Number if address as f64 <= MAX_SAFE_INTEGER => Ok(cx.number(address as f64).upcast()),
+        AddressFormat::Number | AddressFormat::Hex => Ok(cx.string(format!("0x{:x}", address)).upcast()),
+        AddressFormat::BigInt => {
+            let bigint: Handle<JsFunction> = cx.global().get(cx, "BigInt")?;
+            let undefined = cx.undefined();
+            let hex = cx.string(format!("0x{:x}", address));
+            bigint.call(cx, undefined, [hex.upcast::<JsValue>()])
+        }
+    }
+}
+
+fn address_array<'a>(cx: &mut FunctionContext<'a>, array: Handle<'a, JsArray>, field: &str) -> NeonResult<Vec<u64>> {
+    let values = array.to_vec(cx)?;
+    values
+        .into_iter()
+        .enumerate()
+        .map(|(i, value)| address_value(cx, value, &format!("{}[{}]", field, i)))
+        .collect()
+}
+
+fn text_segment_argument(cx: &mut FunctionContext, i: i32) -> NeonResult<bool> {
+    match cx.argument_opt(i) {
+        Some(value) if !value.is_a::<JsUndefined, _>(cx) => {
+            Ok(downcast_field::<JsBoolean>(cx, value, "text_segment", "a boolean")?.value(cx))
+        }
+        _ => Ok(false),
+    }
+}
+
+fn optional_number<'a, C: Context<'a>>(cx: &mut C, value: Option<u64>) -> Handle<'a, JsValue> {
+    match value {
+        Some(value) => cx.number(value as f64).upcast(),
+        None => cx.null().upcast(),
+    }
+}
+
+fn frame_object<'a, C: Context<'a>>(
+    cx: &mut C,
+    obj: Handle<'a, JsObject>,
+    frame: &Frame,
+    format: AddressFormat,
+) -> NeonResult<()> {
+    let formatted = cx.string(frame.to_string());
+    let symbol = cx.string(&frame.symbol);
+    let demangled_symbol = cx.string(&frame.demangled_symbol);
+    let module = cx.string(&frame.module);
+    let file: Handle<JsValue> = match frame.file {
+        Some(ref file) => cx.string(file).upcast(),
+        None => cx.null().upcast(),
+    };
+    let line = optional_number(cx, frame.line);
+    let column = optional_number(cx, frame.column);
+    let symbol_offset = cx.number(frame.symbol_offset as f64);
+    let symbol_address = address_output(cx, format, frame.symbol_address)?;
+    let lookup = cx.string(match frame.lookup {
+        Lookup::Dwarf => "dwarf",
+        Lookup::SymbolTable => "symbol_table",
+    });
+    obj.set(cx, "formatted", formatted)?;
+    obj.set(cx, "symbol", symbol)?;
+    obj.set(cx, "demangledSymbol", demangled_symbol)?;
+    obj.set(cx, "module", module)?;
+    obj.set(cx, "file", file)?;
+    obj.set(cx, "line", line)?;
+    obj.set(cx, "column", column)?;
+    obj.set(cx, "symbolOffset", symbol_offset)?;
+    obj.set(cx, "symbolAddress", symbol_address)?;
+    obj.set(cx, "lookup", lookup)?;
+    Ok(())
+}
+
+fn response_object<'a, C: Context<'a>>(
+    cx: &mut C,
+    result: Result<Vec<ResponseResult>, anyhow::Error>,
+    format: AddressFormat,
+) -> JsResult<'a, JsObject> {
+    match result {
+        Ok(parse_result) => {
+            let response_array = JsArray::new(cx, parse_result.len() as u32);
+            let result_obj = cx.empty_object();
+            for (i, result_instance) in parse_result.iter().enumerate() {
+                let obj = cx.empty_object();
+                let address_number = address_output(cx, format, result_instance.address)?;
+                let result_string = cx.string(&result_instance.result);
+                obj.set(cx, "address", address_number)?;
+                obj.set(cx, "result", result_string)?;
+                if let Some(frame) = &result_instance.frame {
+                    frame_object(cx, obj, frame, format)?;
+                }
+                let error: Handle<JsValue> = match &result_instance.error {
+                    Some(error) => cx.string(error).upcast(),
+                    None => cx.null().upcast(),
+                };
+                obj.set(cx, "error", error)?;
+                let inlined = JsArray::new(cx, result_instance.inlined.len() as u32);
+                for (j, inlined_frame) in result_instance.inlined.iter().enumerate() {
+                    let inlined_obj = cx.empty_object();
+                    frame_object(cx, inlined_obj, inlined_frame, format)?;
+                    inlined.set(cx, j as u32, inlined_obj)?;
+                }
+                obj.set(cx, "inlined", inlined)?;
+                response_array.set(cx, i as u32, obj)?;
+            }
+            let success = cx.boolean(true);
+            let data = response_array;
+            let message = cx.null();
+            result_obj.set(cx, "success", success)?;
+            result_obj.set(cx, "data", data)?;
+            result_obj.set(cx, "message", message)?;
+            Ok(result_obj)
+        },
+        Err(err) => {
+            let result_obj = cx.empty_object();
+            let success = cx.boolean(false);
+            let data = cx.empty_array();
+            let message = cx.string(err.to_string());
+            result_obj.set(cx, "success", success)?;
+            result_obj.set(cx, "data", data)?;
+            result_obj.set(cx, "message", message)?;
+            Ok(result_obj)
+        },
+    }
+}
+
+// module name of objects given as a buffer without `module`
+const BUFFER_MODULE: &str = "buffer";
+
+// `file` is a path, or a Buffer / Uint8Array holding an object or a zipped
+// dSYM archive, reported under the module `module`
+enum FileArgument<'a> {
+    Path(String),
+    Buffer(Handle<'a, JsTypedArray<u8>>, String),
+}
+
+// a FileArgument copied out of the JS heap, for tasks and handles that
+// outlive the call
+enum OwnedFile {
+    Path(String),
+    Buffer(Vec<u8>, String),
+}
+
+impl<'a> FileArgument<'a> {
+    // the buffer is borrowed, not copied, for the duration of `f`
+    fn with_input<T>(&self, cx: &FunctionContext<'a>, f: impl FnOnce(&ObjectInput) -> T) -> T {
+        match self {
+            FileArgument::Path(path) => f(&ObjectInput::Path(path)),
+            FileArgument::Buffer(buffer, name) => f(&ObjectInput::Bytes {
+                data: buffer.as_slice(cx),
+                name,
+            }),
+        }
+    }
+
+    fn to_owned(&self, cx: &FunctionContext<'a>) -> OwnedFile {
+        match self {
+            FileArgument::Path(path) => OwnedFile::Path(path.clone()),
+            FileArgument::Buffer(buffer, name) => OwnedFile::Buffer(buffer.as_slice(cx).to_vec(), name.clone()),
+        }
+    }
+}
+
+impl OwnedFile {
+    fn input(&self) -> ObjectInput<'_> {
+        match self {
+            OwnedFile::Path(path) => ObjectInput::Path(path),
+            OwnedFile::Buffer(data, name) => ObjectInput::Bytes { data, name },
+        }
+    }
+}
+
+fn file_argument<'a>(
+    cx: &mut FunctionContext<'a>,
+    value: Handle<'a, JsValue>,
+    module: Option<String>,
+) -> NeonResult<FileArgument<'a>> {
+    // Buffer is a Uint8Array
+    if let Ok(buffer) = value.downcast::<JsTypedArray<u8>, _>(cx) {
+        let module = module.unwrap_or_else(|| BUFFER_MODULE.to_string());
+        return Ok(FileArgument::Buffer(buffer, module));
+    }
+    let path = downcast_field::<JsString>(cx, value, "file", "a path string, Buffer or Uint8Array")?;
+    Ok(FileArgument::Path(path.value(cx)))
+}
+
+struct ParseArguments<'a> {
+    file: FileArgument<'a>,
+    arch: Option<String>,
+    uuid: Option<String>,
+    load_address: u64,
+    addresses: Vec<u64>,
+    offset_text_segment: bool,
+    address_format: AddressFormat,
+}
+
+fn optional_string(cx: &mut FunctionContext, obj: Handle<JsObject>, key: &str) -> NeonResult<Option<String>> {
+    let value = optional_field::<JsString>(cx, obj, "", key, "a string")?;
+    Ok(value.map(|value| value.value(cx)))
+}
+
+fn optional_string_array(cx: &mut FunctionContext, obj: Handle<JsObject>, key: &str) -> NeonResult<Vec<String>> {
+    let value = optional_field::<JsArray>(cx, obj, "", key, "an array of strings")?;
+    match value {
+        Some(value) => value
+            .to_vec(cx)?
+            .into_iter()
+            .enumerate()
+            .map(|(i, v)| {
+                let field = format!("{}[{}]", key, i);
+                downcast_field::<JsString>(cx, v, &field, "a string").map(|s| s.value(cx))
+            })
+            .collect(),
+        None => Ok(Vec::new()),
+    }
+}
+
+fn parse_arguments<'a>(cx: &mut FunctionContext<'a>) -> NeonResult<ParseArguments<'a>> {
+    let params = object_argument(cx, 0, "option")?;
+    let params_file = required_field::<JsValue>(cx, params, "", "file", "a path string, Buffer or Uint8Array")?;
+    let params_load_address = required_field::<JsValue>(cx, params, "", "load_address", "an address string, number or BigInt")?;
+    let params_addresses = required_field::<JsArray>(cx, params, "", "addresses", "an array of addresses")?;
+    let arch = optional_string(cx, params, "arch")?;
+    let uuid = optional_string(cx, params, "uuid")?;
+    let params_offset_text_segment = text_segment_argument(cx, 1)?;
+    let module = optional_string(cx, params, "module")?;
+    let address_format = address_format_option(cx, params)?;
+    let file = file_argument(cx, params_file, module)?;
+    let load_address = address_value(cx, params_load_address, "load_address")?;
+    let addresses = address_array(cx, params_addresses, "addresses")?;
+    Ok(ParseArguments {
+        file,
+        arch,
+        uuid,
+        load_address,
+        addresses,
+        offset_text_segment: params_offset_text_segment,
+        address_format,
+    })
+}
+
+fn parse(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let ParseArguments { file, arch, uuid, load_address, addresses, offset_text_segment, address_format } = parse_arguments(&mut cx)?;
+    let result = file.with_input(&cx, |input| atosl::print_addresses(
+        input,
+        arch.as_deref(),
+        uuid.as_deref(),
+        load_address,
+        addresses,
+        offset_text_segment
+    ));
+    response_object(&mut cx, result, address_format)
+}
+
+fn parse_async(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let args = parse_arguments(&mut cx)?;
+    let file = args.file.to_owned(&cx);
+    let ParseArguments { arch, uuid, load_address, addresses, offset_text_segment, address_format, .. } = args;
+    let promise = cx
+        .task(move || atosl::print_addresses(
+            &file.input(),
+            arch.as_deref(),
+            uuid.as_deref(),
+            load_address,
+            addresses,
+            offset_text_segment
+        ))
+        .promise(move |mut cx, result| response_object(&mut cx, result, address_format));
+    Ok(promise)
+}
+
+// `prefix` is the path of `obj` in the arguments, used in error messages
+fn transform_group_address(obj: &Handle<JsObject>, cx: &mut FunctionContext, prefix: &str) -> NeonResult<GroupAddress> {
+    let load_address = required_field::<JsValue>(cx, *obj, prefix, "load_address", "an address string, number or BigInt")?;
+    let params_addresses = required_field::<JsArray>(cx, *obj, prefix, "addresses", "an array of addresses")?;
+    let load_address_u64 = address_value(cx, load_address, &field_name(prefix, "load_address"))?;
+    let addresses = address_array(cx, params_addresses, &field_name(prefix, "addresses"))?;
+    let uuid = optional_field::<JsString>(cx, *obj, prefix, "uuid", "a string")?.map(|uuid| uuid.value(cx));
+    Ok(GroupAddress {
+        load_address: load_address_u64,
+        addresses,
+        uuid,
+    })
+}
+
+struct GroupParseArguments<'a> {
+    file: FileArgument<'a>,
+    arch: Option<String>,
+    uuid: Option<String>,
+    debug_dirs: Vec<String>,
+    addresses: Vec<GroupAddress>,
+    offset_text_segment: bool,
+    address_format: AddressFormat,
+}
+
+fn group_parse_arguments<'a>(cx: &mut FunctionContext<'a>) -> NeonResult<GroupParseArguments<'a>> {
+    let params = object_argument(cx, 0, "option")?;
+    let params_file = required_field::<JsValue>(cx, params, "", "file", "a path string, Buffer or Uint8Array")?;
+    let params_addresses = required_field::<JsArray>(cx, params, "", "addresses", "an array of address groups")?;
+    let arch = optional_string(cx, params, "arch")?;
+    let uuid = optional_string(cx, params, "uuid")?;
+    let debug_dirs = optional_string_array(cx, params, "debug_dirs")?;
+    let params_offset_text_segment = text_segment_argument(cx, 1)?;
+    let module = optional_string(cx, params, "module")?;
+    let address_format = address_format_option(cx, params)?;
+    let file = file_argument(cx, params_file, module)?;
+    let addresses = params_addresses
+        .to_vec(cx)?
+        .into_iter()
+        .enumerate()
+        .map(|(i, v)| {
+            let prefix = format!("addresses[{}]", i);
+            let group = downcast_field::<JsObject>(cx, v, &prefix, "an object")?;
+            transform_group_address(&group, cx, &prefix)
+        })
+        .collect::<NeonResult<Vec<_>>>()?;
+    Ok(GroupParseArguments {
+        file,
+        arch,
+        uuid,
+        debug_dirs,
+        addresses,
+        offset_text_segment: params_offset_text_segment,
+        address_format,
+    })
+}
+
+fn group_parse(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let GroupParseArguments { file, arch, uuid, debug_dirs, addresses, offset_text_segment, address_format } = group_parse_arguments(&mut cx)?;
+    let result = file.with_input(&cx, |input| atosl::parse_addresses(
+        input,
+        arch.as_deref(),
+        uuid.as_deref(),
+        &debug_dirs,
+        addresses,
+        offset_text_segment
+    ));
+    response_object(&mut cx, result, address_format)
+}
+
+fn group_parse_async(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let args = group_parse_arguments(&mut cx)?;
+    let file = args.file.to_owned(&cx);
+    let GroupParseArguments { arch, uuid, debug_dirs, addresses, offset_text_segment, address_format, .. } = args;
+    let promise = cx
+        .task(move || atosl::parse_addresses(
+            &file.input(),
+            arch.as_deref(),
+            uuid.as_deref(),
+            &debug_dirs,
+            addresses,
+            offset_text_segment
+        ))
+        .promise(move |mut cx, result| response_object(&mut cx, result, address_format));
+    Ok(promise)
+}
+
+fn report_object<'a, C: Context<'a>>(cx: &mut C, result: Result<String, anyhow::Error>) -> JsResult<'a, JsObject> {
+    let result_obj = cx.empty_object();
+    match result {
+        Ok(symbolicated) => {
+            let success = cx.boolean(true);
+            let data = cx.string(symbolicated);
+            let message = cx.null();
+            result_obj.set(cx, "success", success)?;
+            result_obj.set(cx, "data", data)?;
+            result_obj.set(cx, "message", message)?;
+        }
+        Err(err) => {
+            let success = cx.boolean(false);
+            let data = cx.null();
+            let message = cx.string(err.to_string());
+            result_obj.set(cx, "success", success)?;
+            result_obj.set(cx, "data", data)?;
+            result_obj.set(cx, "message", message)?;
+        }
+    }
+    Ok(result_obj)
+}
+
+// atosl.symbolicateCrash(report, dsym_dir) => { success, data: string, message }
+fn symbolicate_crash(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let report = string_argument(&mut cx, 0, "report")?;
+    let dsym_dir = string_argument(&mut cx, 1, "dsym_dir")?;
+    let result = crash::symbolicate_crash_report(&report, &dsym_dir);
+    report_object(&mut cx, result)
+}
+
+// atosl.symbolicateIps(report, dsym_dir) => { success, data: string, message }
+fn symbolicate_ips(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let report = string_argument(&mut cx, 0, "report")?;
+    let dsym_dir = string_argument(&mut cx, 1, "dsym_dir")?;
+    let result = ips::symbolicate_ips_report(&report, &dsym_dir);
+    report_object(&mut cx, result)
+}
+
+// atosl.symbolicateTombstone(report, so_dir) => { success, data: string, message }
+fn symbolicate_tombstone(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let report = string_argument(&mut cx, 0, "report")?;
+    let so_dir = string_argument(&mut cx, 1, "so_dir")?;
+    let result = tombstone::symbolicate_tombstone(&report, &so_dir);
+    report_object(&mut cx, result)
+}
+
+// atosl.open(file, { arch, uuid, module }?) => { symbolicate(option, text_segment?), close() }
+fn open(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let (arch, uuid, module, address_format) = match cx.argument_opt(1) {
+        Some(options) if !options.is_a::<JsUndefined, _>(&mut cx) => {
+            let options = downcast_field::<JsObject>(&mut cx, options, "options", "an object")?;
+            (
+                optional_string(&mut cx, options, "arch")?,
+                optional_string(&mut cx, options, "uuid")?,
+                optional_string(&mut cx, options, "module")?,
+                address_format_option(&mut cx, options)?,
+            )
+        }
+        _ => (None, None, None, AddressFormat::Number),
+    };
+    let file = match cx.argument_opt(0) {
+        Some(file) => file,
+        None => return cx.throw_type_error("file is required"),
+    };
+    // the handle keeps its own copy of a buffer
+    let file = file_argument(&mut cx, file, module)?.to_owned(&cx);
+    let symbolicator = match file {
+        OwnedFile::Path(path) => Symbolicator::open(&path, arch.as_deref(), uuid.as_deref(), &[]),
+        OwnedFile::Buffer(data, name) => Symbolicator::from_vec(data, &name, arch.as_deref(), uuid.as_deref()),
+    };
+    let symbolicator = match symbolicator {
+        Ok(symbolicator) => symbolicator,
+        Err(err) => return cx.throw_error(err.to_string()),
+    };
+    let handle = cx.boxed((RefCell::new(Some(symbolicator)), address_format));
+    let symbolicate = JsFunction::new(&mut cx, symbolicator_symbolicate)?;
+    let close = JsFunction::new(&mut cx, symbolicator_close)?;
+    let obj = cx.empty_object();
+    obj.set(&mut cx, "handle", handle)?;
+    obj.set(&mut cx, "symbolicate", symbolicate)?;
+    obj.set(&mut cx, "close", close)?;
+    Ok(obj)
+}
+
+fn this_symbolicator<'a>(cx: &mut FunctionContext<'a>) -> JsResult<'a, BoxedSymbolicator> {
+    let this = cx.this();
+    this.get(cx, "handle")
+}
+
+fn symbolicator_symbolicate(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let handle = this_symbolicator(&mut cx)?;
+    let params = object_argument(&mut cx, 0, "option")?;
+    let grouped = transform_group_address(&params, &mut cx, "")?;
+    let params_offset_text_segment = text_segment_argument(&mut cx, 1)?;
+    let (symbolicator, address_format) = &**handle;
+    let result = match *symbolicator.borrow() {
+        Some(ref symbolicator) => symbolicator.symbolicate(
+            grouped.load_address,
+            grouped.addresses,
+            params_offset_text_segment,
+        ),
+        None => return cx.throw_error("symbolicator is closed"),
+    };
+    response_object(&mut cx, result, *address_format)
+}
+
+fn symbolicator_close(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let handle = this_symbolicator(&mut cx)?;
+    // dropping the symbolicator unmaps the file right away instead of
+    // waiting for the garbage collector
+    handle.0.borrow_mut().take();
+    Ok(cx.undefined())
+}
+
+// the optional symbol server of a store, from
+// { server_url, server_layout?, cache_dir?, cache_size? }
+fn symbol_server_argument(cx: &mut FunctionContext, dir: &str, options: Option<Handle<JsObject>>) -> NeonResult<Option<SymbolServer>> {
+    let options = match options {
+        Some(options) => options,
+        None => return Ok(None),
+    };
+    let url = match optional_string(cx, options, "server_url")? {
+        Some(url) => url,
+        None => return Ok(None),
+    };
+    let layout = optional_string(cx, options, "server_layout")?;
+    let layout = match ServerLayout::parse(layout.as_deref().unwrap_or("debuginfod")) {
+        Ok(layout) => layout,
+        Err(err) => return cx.throw_error(err.to_string()),
+    };
+    let cache_dir = match optional_string(cx, options, "cache_dir")? {
+        Some(cache_dir) => cache_dir.into(),
+        // inside the store, so downloads are indexed with it next time
+        None => std::path::Path::new(dir).join(".atosl-cache"),
+    };
+    let cache_size = optional_field::<JsNumber>(cx, options, "", "cache_size", "a number")?;
+    let cache_size = match cache_size.map(|size| size.value(cx)) {
+        Some(size) if size < 0.0 || size.is_nan() => {
+            return cx.throw_range_error(format!("cache_size must be a non-negative number, got {}", size))
+        }
+        size => size.map(|size| size as u64),
+    };
+    Ok(Some(SymbolServer {
+        url,
+        layout,
+        cache_dir,
+        cache_size,
+    }))
+}
+
+// atosl.openStore(dir, { server_url, server_layout, cache_dir, cache_size, address_format }?)
+//   => { lookup(uuid), symbolicate(uuid, load_address, addresses, text_segment?) }
+fn open_store(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let dir = string_argument(&mut cx, 0, "dir")?;
+    let options = match cx.argument_opt(1) {
+        Some(options) if !options.is_a::<JsUndefined, _>(&mut cx) => {
+            Some(downcast_field::<JsObject>(&mut cx, options, "options", "an object")?)
+        }
+        _ => None,
+    };
+    let address_format = match options {
+        Some(options) => address_format_option(&mut cx, options)?,
+        None => AddressFormat::Number,
+    };
+    let server = symbol_server_argument(&mut cx, &dir, options)?;
+    let store = match SymbolStore::open(&dir, server) {
+        Ok(store) => store,
+        Err(err) => return cx.throw_error(err.to_string()),
+    };
+    let handle = cx.boxed((store, address_format));
+    let lookup = JsFunction::new(&mut cx, store_lookup)?;
+    let symbolicate = JsFunction::new(&mut cx, store_symbolicate)?;
+    let obj = cx.empty_object();
+    obj.set(&mut cx, "handle", handle)?;
+    obj.set(&mut cx, "lookup", lookup)?;
+    obj.set(&mut cx, "symbolicate", symbolicate)?;
+    Ok(obj)
+}
+
+fn this_store<'a>(cx: &mut FunctionContext<'a>) -> JsResult<'a, BoxedStore> {
+    let this = cx.this();
+    this.get(cx, "handle")
+}
+
+// store.lookup(uuid) => path of the object | null
+// blocks on the download when the id is only on the symbol server, bounded
+// by the timeouts of `SymbolServer::fetch`
+fn store_lookup(mut cx: FunctionContext) -> JsResult<JsValue> {
+    let store = this_store(&mut cx)?;
+    let id = string_argument(&mut cx, 0, "uuid")?;
+    match store.0.lookup(&id) {
+        Ok(Some(entry)) => Ok(cx.string(entry.path.to_string_lossy()).upcast()),
+        Ok(None) => Ok(cx.null().upcast()),
+        Err(err) => cx.throw_error(err.to_string()),
+    }
+}
+
+// blocks like store.lookup
+fn store_symbolicate(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let store = this_store(&mut cx)?;
+    let id = string_argument(&mut cx, 0, "uuid")?;
+    let load_address = match cx.argument_opt(1) {
+        Some(load_address) => address_value(&mut cx, load_address, "load_address")?,
+        None => return cx.throw_type_error("load_address is required"),
+    };
+    let params_addresses = match cx.argument_opt(2) {
+        Some(addresses) => downcast_field::<JsArray>(&mut cx, addresses, "addresses", "an array of addresses")?,
+        None => return cx.throw_type_error("addresses is required"),
+    };
+    let addresses = address_array(&mut cx, params_addresses, "addresses")?;
+    let params_offset_text_segment = text_segment_argument(&mut cx, 3)?;
+    let (store, address_format) = &**store;
+    let result = store.symbolicate(&id, load_address, addresses, params_offset_text_segment);
+    response_object(&mut cx, result, *address_format)
+}
+
+#[neon::main]
+fn main(mut cx: ModuleContext) -> NeonResult<()> {
+    cx.export_function("parse", parse)?;
+    cx.export_function("groupParse", group_parse)?;
+    cx.export_function("parseAsync", parse_async)?;
+    cx.export_function("groupParseAsync", group_parse_async)?;
+    cx.export_function("open", open)?;
+    cx.export_function("openStore", open_store)?;
+    cx.export_function("symbolicateCrash", symbolicate_crash)?;
+    cx.export_function("symbolicateIps", symbolicate_ips)?;
+    cx.export_function("symbolicateTombstone", symbolicate_tombstone)?;
+    Ok(())
+}
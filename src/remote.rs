@@ -0,0 +1,183 @@
+use crate::{elf, macho, store};
+use anyhow::{anyhow, Result};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+// downloads run on the caller's thread, an unresponsive server must not
+// hold it forever; the read timeout applies to each read, not the transfer
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How a symbol server lays out its files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ServerLayout {
+    /// `<url>/buildid/<id>/debuginfo`
+    Debuginfod,
+    /// Microsoft symbol server (SSQP) keys:
+    /// `<url>/_.dwarf/mach-uuid-sym-<id>/_.dwarf` for dSYMs and
+    /// `<url>/_.debug/elf-buildid-sym-<id>/_.debug` for ELF debug files
+    Ssqp,
+}
+
+impl ServerLayout {
+    pub fn parse(layout: &str) -> Result<ServerLayout> {
+        match layout {
+            "debuginfod" => Ok(ServerLayout::Debuginfod),
+            "ssqp" => Ok(ServerLayout::Ssqp),
+            _ => Err(anyhow!("unknown symbol server layout {}", layout)),
+        }
+    }
+
+    // the urls to try for a normalized id, in order
+    fn urls(&self, url: &str, id: &str) -> Vec<String> {
+        let url = url.trim_end_matches('/');
+        match self {
+            ServerLayout::Debuginfod => vec![format!("{}/buildid/{}/debuginfo", url, id)],
+            ServerLayout::Ssqp => {
+                let mut urls = Vec::new();
+                // a Mach-O uuid is 16 bytes, build-ids are usually longer
+                if id.len() == 32 {
+                    urls.push(format!("{}/_.dwarf/mach-uuid-sym-{}/_.dwarf", url, id));
+                }
+                urls.push(format!("{}/_.debug/elf-buildid-sym-{}/_.debug", url, id));
+                urls
+            }
+        }
+    }
+}
+
+/// An HTTP symbol server whose downloads are kept in `cache_dir`, which is
+/// trimmed to `cache_size` bytes by evicting the least recently used
+/// objects. Each object is cached as `<cache_dir>/<id>/<name>`, named after
+/// its SONAME or install name when it records one so that results are
+/// reported under the library's name, otherwise after the id.
+pub struct SymbolServer {
+    pub url: String,
+    pub layout: ServerLayout,
+    pub cache_dir: PathBuf,
+    pub cache_size: Option<u64>,
+}
+
+// the name an object calls itself, if it is a plain file name
+fn object_name(path: &Path) -> Option<String> {
+    let file = fs::File::open(path).ok()?;
+    let mmap = unsafe { memmap::Mmap::map(&file).ok()? };
+    let data = match macho::fat_slices(&mmap).ok()?.first() {
+        Some(&(_, slice)) => slice,
+        None => &mmap[..],
+    };
+    let name = match elf::object_soname(data).ok()? {
+        Some(name) => name,
+        None => macho::install_name(data).ok()??,
+    };
+    // an install name is a path, only its last component names the file
+    let name = Path::new(&name).file_name()?.to_str()?;
+    if name.ends_with(".part") {
+        return None;
+    }
+    Some(name.to_string())
+}
+
+impl SymbolServer {
+    fn entry_dir(&self, id: &str) -> PathBuf {
+        self.cache_dir.join(id)
+    }
+
+    // the object cached for an id, whatever it is named
+    fn cached(&self, id: &str) -> Option<PathBuf> {
+        let mut paths: Vec<PathBuf> = fs::read_dir(self.entry_dir(id))
+            .ok()?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file() && path.extension().is_none_or(|extension| extension != "part"))
+            .collect();
+        paths.sort();
+        paths.into_iter().next()
+    }
+
+    /// The cached or downloaded object for a normalized id and whether it
+    /// was just downloaded, None when the server does not have it.
+    pub fn fetch(&self, id: &str) -> Result<Option<(PathBuf, bool)>> {
+        if !store::is_valid_id(id) {
+            return Err(anyhow!("invalid id {:?}, expected a hex UUID or build-id", id));
+        }
+        if let Some(path) = self.cached(id) {
+            // the modification time records the last use for eviction
+            fs::File::options().append(true).open(&path)?.set_modified(SystemTime::now())?;
+            return Ok(Some((path, false)));
+        }
+        let agent = ureq::AgentBuilder::new()
+            .timeout_connect(CONNECT_TIMEOUT)
+            .timeout_read(READ_TIMEOUT)
+            .build();
+        for url in self.layout.urls(&self.url, id) {
+            let response = match agent.get(&url).call() {
+                Ok(response) => response,
+                Err(ureq::Error::Status(404, _)) => continue,
+                Err(err) => return Err(err.into()),
+            };
+            let dir = self.entry_dir(id);
+            fs::create_dir_all(&dir)?;
+            // download next to the final path so that an interrupted
+            // transfer never looks like a cached object
+            let partial = dir.join(format!("{}.part", id));
+            let mut reader = response.into_reader();
+            if let Err(err) = fs::File::create(&partial).and_then(|mut file| io::copy(&mut reader, &mut file)) {
+                let _ = fs::remove_dir_all(&dir);
+                return Err(err.into());
+            }
+            let path = dir.join(object_name(&partial).unwrap_or_else(|| id.to_string()));
+            fs::rename(&partial, &path)?;
+            self.trim_cache(id)?;
+            return Ok(Some((path, true)));
+        }
+        Ok(None)
+    }
+
+    /// Removes the cached object of an id, e.g. one that turned out not to
+    /// carry it.
+    pub fn evict(&self, id: &str) -> Result<()> {
+        let dir = self.entry_dir(id);
+        if dir.is_dir() {
+            fs::remove_dir_all(&dir)?;
+        }
+        Ok(())
+    }
+
+    // removes the least recently used objects until the cache fits, never
+    // the one that was just downloaded
+    fn trim_cache(&self, keep: &str) -> Result<()> {
+        let limit = match self.cache_size {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+        let mut objects = Vec::new();
+        for entry in fs::read_dir(&self.cache_dir)? {
+            let entry = entry?;
+            let id = match entry.file_name().to_str() {
+                Some(id) if entry.file_type()?.is_dir() && store::is_valid_id(id) => id.to_string(),
+                _ => continue,
+            };
+            let (mut used, mut size) = (SystemTime::UNIX_EPOCH, 0);
+            for file in fs::read_dir(entry.path())? {
+                let metadata = file?.metadata()?;
+                used = used.max(metadata.modified()?);
+                size += metadata.len();
+            }
+            objects.push((used, size, id));
+        }
+        let mut total: u64 = objects.iter().map(|(_, size, _)| size).sum();
+        objects.sort();
+        for (_, size, id) in objects {
+            if total <= limit {
+                break;
+            }
+            if id != keep {
+                self.evict(&id)?;
+                total -= size;
+            }
+        }
+        Ok(())
+    }
+}
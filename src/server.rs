@@ -0,0 +1,368 @@
+use crate::atosl::{parse_address_string, Frame, Lookup, ResponseResult, Symbolicator};
+use anyhow::{anyhow, Result};
+use serde_json::{json, Map, Value};
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// Objects kept open between requests when no other limit is given.
+pub const DEFAULT_CACHE_SIZE: usize = 16;
+
+// what a cached object was opened with
+#[derive(Clone, PartialEq, Eq)]
+struct CacheKey {
+    file: String,
+    arch: Option<String>,
+    uuid: Option<String>,
+    debug_dirs: Vec<String>,
+}
+
+// size and mtime of the file when it was opened, a rebuilt object replaces
+// the cached one
+type FileStamp = Option<(u64, SystemTime)>;
+
+fn file_stamp(file: &str) -> FileStamp {
+    let metadata = fs::metadata(file).ok()?;
+    Some((metadata.len(), metadata.modified().ok()?))
+}
+
+struct CacheEntry {
+    key: CacheKey,
+    stamp: FileStamp,
+    symbolicator: Arc<Symbolicator<'static>>,
+}
+
+/// A JSON-lines symbolication service. Every request line
+///
+/// `{"id"?, "file", "arch"?, "uuid"?, "debug_dirs"?, "load_address", "addresses", "text_segment"?}`
+///
+/// is answered by one line `{"id", "success", "data", "message"}` with the
+/// entries of `atosl.parse`. Addresses are given as `0x` hex or decimal
+/// strings or as numbers, and returned as hex strings so that no client
+/// loses precision. The most recently used objects stay open between
+/// requests.
+pub struct Server {
+    cache_size: usize,
+    // least recently used first
+    cache: Mutex<Vec<CacheEntry>>,
+}
+
+struct Request {
+    key: CacheKey,
+    load_address: u64,
+    addresses: Vec<u64>,
+    text_segment: bool,
+}
+
+fn optional_string(request: &Map<String, Value>, key: &str) -> Result<Option<String>> {
+    match request.get(key) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(value)) => Ok(Some(value.clone())),
+        Some(_) => Err(anyhow!("{} must be a string", key)),
+    }
+}
+
+fn address_value(value: &Value, field: &str) -> Result<u64> {
+    match value {
+        Value::String(address) => parse_address_string(address)
+            .map_err(|err| anyhow!("{} is not a valid address ({:?}): {}", field, address, err)),
+        Value::Number(number) => number
+            .as_u64()
+            .ok_or_else(|| anyhow!("{} is not a valid address ({})", field, number)),
+        _ => Err(anyhow!("{} must be an address string or number", field)),
+    }
+}
+
+fn parse_request(request: &Map<String, Value>) -> Result<Request> {
+    let file = optional_string(request, "file")?.ok_or_else(|| anyhow!("file is required"))?;
+    let debug_dirs = match request.get("debug_dirs") {
+        None | Some(Value::Null) => Vec::new(),
+        Some(Value::Array(dirs)) => dirs
+            .iter()
+            .enumerate()
+            .map(|(i, dir)| match dir {
+                Value::String(dir) => Ok(dir.clone()),
+                _ => Err(anyhow!("debug_dirs[{}] must be a string", i)),
+            })
+            .collect::<Result<_>>()?,
+        Some(_) => return Err(anyhow!("debug_dirs must be an array of strings")),
+    };
+    let load_address = match request.get("load_address") {
+        Some(value) => address_value(value, "load_address")?,
+        None => return Err(anyhow!("load_address is required")),
+    };
+    let addresses = match request.get("addresses") {
+        Some(Value::Array(addresses)) => addresses
+            .iter()
+            .enumerate()
+            .map(|(i, address)| address_value(address, &format!("addresses[{}]", i)))
+            .collect::<Result<_>>()?,
+        Some(_) => return Err(anyhow!("addresses must be an array of addresses")),
+        None => return Err(anyhow!("addresses is required")),
+    };
+    let text_segment = match request.get("text_segment") {
+        None | Some(Value::Null) => false,
+        Some(Value::Bool(text_segment)) => *text_segment,
+        Some(_) => return Err(anyhow!("text_segment must be a boolean")),
+    };
+    Ok(Request {
+        key: CacheKey {
+            file,
+            arch: optional_string(request, "arch")?,
+            uuid: optional_string(request, "uuid")?,
+            debug_dirs,
+        },
+        load_address,
+        addresses,
+        text_segment,
+    })
+}
+
+fn hex(address: u64) -> Value {
+    json!(format!("0x{:x}", address))
+}
+
+// the same keys as the frames of atosl.parse
+fn frame_json(frame: &Frame, object: &mut Map<String, Value>) {
+    object.insert("formatted".to_string(), json!(frame.to_string()));
+    object.insert("symbol".to_string(), json!(frame.symbol));
+    object.insert("demangledSymbol".to_string(), json!(frame.demangled_symbol));
+    object.insert("module".to_string(), json!(frame.module));
+    object.insert("file".to_string(), json!(frame.file));
+    object.insert("line".to_string(), json!(frame.line));
+    object.insert("column".to_string(), json!(frame.column));
+    object.insert("symbolOffset".to_string(), json!(frame.symbol_offset));
+    object.insert("symbolAddress".to_string(), hex(frame.symbol_address));
+    object.insert(
+        "lookup".to_string(),
+        json!(match frame.lookup {
+            Lookup::Dwarf => "dwarf",
+            Lookup::SymbolTable => "symbol_table",
+        }),
+    );
+}
+
+fn result_json(result: &ResponseResult) -> Value {
+    let mut object = Map::new();
+    object.insert("address".to_string(), hex(result.address));
+    object.insert("result".to_string(), json!(result.result));
+    if let Some(ref frame) = result.frame {
+        frame_json(frame, &mut object);
+    }
+    object.insert("error".to_string(), json!(result.error));
+    let inlined = result
+        .inlined
+        .iter()
+        .map(|frame| {
+            let mut object = Map::new();
+            frame_json(frame, &mut object);
+            Value::Object(object)
+        })
+        .collect();
+    object.insert("inlined".to_string(), Value::Array(inlined));
+    Value::Object(object)
+}
+
+impl Server {
+    pub fn new(cache_size: usize) -> Server {
+        Server {
+            cache_size: cache_size.max(1),
+            cache: Mutex::new(Vec::new()),
+        }
+    }
+
+    // the cached object for `key`, opened again when the file changed
+    fn symbolicator(&self, key: &CacheKey) -> Result<Arc<Symbolicator<'static>>> {
+        let stamp = file_stamp(&key.file);
+        {
+            let mut cache = self.cache.lock().unwrap_or_else(|err| err.into_inner());
+            if let Some(index) = cache.iter().position(|entry| entry.key == *key) {
+                let entry = cache.remove(index);
+                if entry.stamp == stamp {
+                    let symbolicator = entry.symbolicator.clone();
+                    cache.push(entry);
+                    return Ok(symbolicator);
+                }
+            }
+        }
+        // opened without holding the lock, other connections keep going
+        let symbolicator = Arc::new(Symbolicator::open(
+            &key.file,
+            key.arch.as_deref(),
+            key.uuid.as_deref(),
+            &key.debug_dirs,
+        )?);
+        let mut cache = self.cache.lock().unwrap_or_else(|err| err.into_inner());
+        cache.retain(|entry| entry.key != *key);
+        if cache.len() >= self.cache_size {
+            cache.remove(0);
+        }
+        cache.push(CacheEntry {
+            key: key.clone(),
+            stamp,
+            symbolicator: symbolicator.clone(),
+        });
+        Ok(symbolicator)
+    }
+
+    fn symbolicate(&self, request: &Map<String, Value>) -> Result<Vec<ResponseResult>> {
+        let request = parse_request(request)?;
+        self.symbolicator(&request.key)?.symbolicate(
+            request.load_address,
+            request.addresses,
+            request.text_segment,
+        )
+    }
+
+    /// Answers one request line.
+    pub fn handle_line(&self, line: &str) -> Value {
+        let request = match serde_json::from_str::<Value>(line) {
+            Ok(Value::Object(request)) => request,
+            Ok(_) => return json!({"id": null, "success": false, "data": [], "message": "request must be a JSON object"}),
+            Err(err) => return json!({"id": null, "success": false, "data": [], "message": format!("invalid JSON: {}", err)}),
+        };
+        // echoed back so that clients can pipeline requests
+        let id = request.get("id").cloned().unwrap_or(Value::Null);
+        match self.symbolicate(&request) {
+            Ok(results) => json!({
+                "id": id,
+                "success": true,
+                "data": results.iter().map(result_json).collect::<Vec<_>>(),
+                "message": null,
+            }),
+            Err(err) => json!({"id": id, "success": false, "data": [], "message": err.to_string()}),
+        }
+    }
+
+    /// Answers request lines from `reader` until it is closed. Blank lines
+    /// are ignored.
+    pub fn serve(&self, reader: impl BufRead, mut writer: impl Write) -> io::Result<()> {
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let response = self.handle_line(&line);
+            writeln!(writer, "{}", response)?;
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn fixture(name: &str) -> String {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name);
+        path.to_str().unwrap().to_string()
+    }
+
+    fn request(server: &Server, request: Value) -> Value {
+        server.handle_line(&request.to_string())
+    }
+
+    #[test]
+    fn echoes_request_ids() {
+        let server = Server::new(DEFAULT_CACHE_SIZE);
+        let file = fixture("libfixture-dwarf5.so");
+        for id in [json!(7), json!("a"), json!({"n": 1})] {
+            let response = request(
+                &server,
+                json!({"id": id, "file": file, "load_address": 0, "addresses": ["0x1157", 4448]}),
+            );
+            assert_eq!(response["id"], id);
+            assert_eq!(response["success"], true, "{}", response);
+            assert_eq!(response["data"][0]["address"], "0x1157");
+            assert_eq!(response["data"][1]["address"], "0x1160");
+        }
+        let response = request(&server, json!({"file": file, "load_address": 0, "addresses": []}));
+        assert_eq!(response["id"], Value::Null);
+        assert_eq!(response["data"], json!([]));
+    }
+
+    #[test]
+    fn rejects_malformed_requests() {
+        let server = Server::new(DEFAULT_CACHE_SIZE);
+        let response = server.handle_line("{\"id\": 1,");
+        assert_eq!(response["id"], Value::Null);
+        assert_eq!(response["success"], false);
+        assert!(response["message"].as_str().unwrap().starts_with("invalid JSON: "));
+        for line in ["[1]", "\"file\"", "null"] {
+            assert_eq!(server.handle_line(line)["message"], "request must be a JSON object");
+        }
+
+        let file = fixture("libfixture-dwarf5.so");
+        let cases = [
+            (json!({"load_address": 0, "addresses": []}), "file is required".to_string()),
+            (json!({"file": 1, "load_address": 0, "addresses": []}), "file must be a string".to_string()),
+            (json!({"file": file, "addresses": []}), "load_address is required".to_string()),
+            (
+                json!({"file": file, "load_address": "0xzz", "addresses": []}),
+                "load_address is not a valid address (\"0xzz\")".to_string(),
+            ),
+            (
+                json!({"file": file, "load_address": -1, "addresses": []}),
+                "load_address is not a valid address (-1)".to_string(),
+            ),
+            (json!({"file": file, "load_address": 0}), "addresses is required".to_string()),
+            (
+                json!({"file": file, "load_address": 0, "addresses": "0x1"}),
+                "addresses must be an array of addresses".to_string(),
+            ),
+            (
+                json!({"file": file, "load_address": 0, "addresses": ["0x1", 1.5]}),
+                "addresses[1] is not a valid address (1.5)".to_string(),
+            ),
+            (
+                json!({"file": file, "load_address": 0, "addresses": [true]}),
+                "addresses[0] must be an address string or number".to_string(),
+            ),
+            (
+                json!({"file": file, "load_address": 0, "addresses": [], "text_segment": 1}),
+                "text_segment must be a boolean".to_string(),
+            ),
+            (
+                json!({"file": file, "load_address": 0, "addresses": [], "debug_dirs": ["a", 1]}),
+                "debug_dirs[1] must be a string".to_string(),
+            ),
+        ];
+        for (mut case, message) in cases {
+            case["id"] = json!("bad");
+            let response = request(&server, case);
+            assert_eq!(response["id"], "bad");
+            assert_eq!(response["success"], false);
+            assert_eq!(response["data"], json!([]));
+            let actual = response["message"].as_str().unwrap();
+            assert!(actual.starts_with(&message), "{} does not start with {}", actual, message);
+        }
+    }
+
+    #[test]
+    fn reopens_changed_files() {
+        let dir = std::env::temp_dir().join(format!("atosl-server-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("libfixture.so");
+        fs::copy(fixture("libfixture-dwarf4.so"), &file).unwrap();
+        let key = CacheKey {
+            file: file.to_str().unwrap().to_string(),
+            arch: None,
+            uuid: None,
+            debug_dirs: Vec::new(),
+        };
+        let server = Server::new(DEFAULT_CACHE_SIZE);
+        let first = server.symbolicator(&key).unwrap();
+        assert!(Arc::ptr_eq(&first, &server.symbolicator(&key).unwrap()));
+        // the fixtures differ in size, so the stamp changes whatever the
+        // resolution of modification times
+        fs::copy(fixture("libfixture-dwarf5.so"), &file).unwrap();
+        let second = server.symbolicator(&key).unwrap();
+        assert!(!Arc::ptr_eq(&first, &second));
+        assert!(Arc::ptr_eq(&second, &server.symbolicator(&key).unwrap()));
+        assert_eq!(server.cache.lock().unwrap().len(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,464 @@
+use crate::atosl::{ResponseResult, Symbolicator};
+use crate::remote::SymbolServer;
+use crate::{dsym, elf};
+use anyhow::{anyhow, Result};
+use serde_json::{json, Value};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+// written to the root of the store, paths in it are relative to that root
+const INDEX_FILE_NAME: &str = ".atosl-index.json";
+const INDEX_VERSION: u64 = 1;
+
+/// An object of the store with a given UUID or build-id.
+#[derive(Clone)]
+pub struct StoreEntry {
+    pub path: PathBuf,
+    // the stripped object when `path` is its separate debug file, results
+    // are reported under its name
+    pub module_path: PathBuf,
+    // Mach-O objects are opened by uuid so that the right slice is used
+    pub macho: bool,
+    pub dwarf: bool,
+}
+
+// what the index remembers about one file, reused while size and mtime
+// stay the same
+struct IndexedFile {
+    size: u64,
+    mtime: u64,
+    entries: Vec<(String, bool, bool)>,
+}
+
+/// A directory tree of dSYMs, executables, `.so` and `.debug` files, indexed
+/// by Mach-O UUID and ELF build-id. The index is kept in the directory so
+/// that reopening the store only rescans files that changed. Ids missing
+/// from the tree are fetched from the symbol server, if there is one.
+pub struct SymbolStore {
+    entries: RefCell<HashMap<String, StoreEntry>>,
+    server: Option<SymbolServer>,
+    // ids the server did not have, not asked for again
+    misses: RefCell<HashSet<String>>,
+}
+
+/// Normalizes a UUID or build-id to lowercase hex without dashes, the form
+/// the index is keyed by.
+pub fn normalize_id(id: &str) -> String {
+    id.chars().filter(|c| *c != '-').collect::<String>().to_ascii_lowercase()
+}
+
+/// Whether `id` is a normalized UUID or build-id. Ids become file names
+/// and URLs of the symbol server, anything else must not get that far.
+pub fn is_valid_id(id: &str) -> bool {
+    !id.is_empty() && id.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+// (id, macho, dwarf) of every object in the file
+fn scan_file(path: &Path) -> Result<Vec<(String, bool, bool)>> {
+    if !dsym::is_macho_file(path) && !elf::is_elf_file(path) {
+        return Ok(Vec::new());
+    }
+    let file = fs::File::open(path)?;
+    let mmap = unsafe { memmap::Mmap::map(&file)? };
+    Ok(dsym::object_ids(&mmap)?
+        .into_iter()
+        .map(|object| (elf::format_build_id(&object.id), object.macho, object.dwarf))
+        .collect())
+}
+
+fn file_stamp(path: &Path) -> Result<(u64, u64)> {
+    let metadata = fs::metadata(path)?;
+    let mtime = metadata.modified()?.duration_since(UNIX_EPOCH)?.as_nanos() as u64;
+    Ok((metadata.len(), mtime))
+}
+
+fn read_index(dir: &Path) -> HashMap<String, IndexedFile> {
+    let mut files = HashMap::new();
+    // a missing or unreadable index only means a full rescan
+    let index: Value = match fs::read(dir.join(INDEX_FILE_NAME))
+        .ok()
+        .and_then(|data| serde_json::from_slice(&data).ok())
+    {
+        Some(index) => index,
+        None => return files,
+    };
+    if index.get("version").and_then(Value::as_u64) != Some(INDEX_VERSION) {
+        return files;
+    }
+    if let Some(indexed) = index.get("files").and_then(Value::as_object) {
+        for (path, file) in indexed {
+            let entries = file
+                .get("objects")
+                .and_then(Value::as_array)
+                .map(|objects| {
+                    objects
+                        .iter()
+                        .filter_map(|object| {
+                            Some((
+                                object.get("id")?.as_str()?.to_string(),
+                                object.get("macho")?.as_bool()?,
+                                object.get("dwarf")?.as_bool()?,
+                            ))
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            files.insert(
+                path.clone(),
+                IndexedFile {
+                    size: file.get("size").and_then(Value::as_u64).unwrap_or(0),
+                    mtime: file.get("mtime").and_then(Value::as_u64).unwrap_or(0),
+                    entries,
+                },
+            );
+        }
+    }
+    files
+}
+
+fn write_index(dir: &Path, files: &HashMap<String, IndexedFile>) -> Result<()> {
+    let mut paths: Vec<&String> = files.keys().collect();
+    paths.sort();
+    let mut indexed = serde_json::Map::new();
+    for path in paths {
+        let file = &files[path];
+        let objects: Vec<Value> = file
+            .entries
+            .iter()
+            .map(|(id, macho, dwarf)| json!({ "id": id, "macho": macho, "dwarf": dwarf }))
+            .collect();
+        indexed.insert(
+            path.clone(),
+            json!({ "size": file.size, "mtime": file.mtime, "objects": objects }),
+        );
+    }
+    let index = json!({ "version": INDEX_VERSION, "files": indexed });
+    fs::write(dir.join(INDEX_FILE_NAME), serde_json::to_vec(&index)?)?;
+    Ok(())
+}
+
+impl SymbolStore {
+    /// Opens the store rooted at `dir`, scanning new and changed files and
+    /// updating the on-disk index.
+    pub fn open(dir: &str, server: Option<SymbolServer>) -> Result<SymbolStore> {
+        let dir = Path::new(dir);
+        if !dir.is_dir() {
+            return Err(anyhow!("symbol store {} is not a directory", dir.display()));
+        }
+        let mut indexed = read_index(dir);
+        let mut paths = Vec::new();
+        dsym::collect_files(dir, &mut paths)?;
+        let mut files = HashMap::new();
+        let mut changed = false;
+        for path in paths {
+            // downloads still in progress, or abandoned ones
+            if path.extension().is_some_and(|extension| extension == "part") {
+                continue;
+            }
+            let relative = match path.strip_prefix(dir).ok().and_then(Path::to_str) {
+                Some(relative) if relative != INDEX_FILE_NAME => relative.to_string(),
+                _ => continue,
+            };
+            let (size, mtime) = file_stamp(&path)?;
+            let file = match indexed.remove(&relative) {
+                Some(file) if file.size == size && file.mtime == mtime => file,
+                _ => {
+                    changed = true;
+                    IndexedFile {
+                        size,
+                        mtime,
+                        entries: scan_file(&path).unwrap_or_default(),
+                    }
+                }
+            };
+            files.insert(relative, file);
+        }
+        // files removed since the index was written
+        changed |= !indexed.is_empty();
+        if changed {
+            // a read-only store can still be used, it is just rescanned
+            // every time
+            let _ = write_index(dir, &files);
+        }
+
+        let mut relatives: Vec<String> = files.keys().cloned().collect();
+        relatives.sort();
+        let mut entries: HashMap<String, StoreEntry> = HashMap::new();
+        for relative in relatives {
+            let file = &files[&relative];
+            for (id, macho, dwarf) in file.entries.iter().cloned() {
+                let path = dir.join(&relative);
+                // prefer an object carrying DWARF over a stripped one with
+                // the same id, e.g. a `.debug` file over its `.so`
+                match entries.get_mut(&id) {
+                    Some(existing) if existing.dwarf && !dwarf => {
+                        existing.module_path = path;
+                    }
+                    Some(existing) if !existing.dwarf && dwarf => {
+                        let module_path = existing.path.clone();
+                        *existing = StoreEntry {
+                            path,
+                            module_path,
+                            macho,
+                            dwarf,
+                        };
+                    }
+                    Some(_) => {}
+                    None => {
+                        entries.insert(
+                            id,
+                            StoreEntry {
+                                module_path: path.clone(),
+                                path,
+                                macho,
+                                dwarf,
+                            },
+                        );
+                    }
+                }
+            }
+        }
+        Ok(SymbolStore {
+            entries: RefCell::new(entries),
+            server,
+            misses: RefCell::new(HashSet::new()),
+        })
+    }
+
+    /// The object with the given UUID or build-id, dashes and case ignored.
+    pub fn lookup(&self, id: &str) -> Result<Option<StoreEntry>> {
+        let id = normalize_id(id);
+        if !is_valid_id(&id) {
+            return Err(anyhow!("invalid id {:?}, expected a hex UUID or build-id", id));
+        }
+        if let Some(entry) = self.entries.borrow().get(&id) {
+            // downloads can be evicted from the cache behind our back
+            if entry.path.is_file() {
+                return Ok(Some(entry.clone()));
+            }
+        }
+        let server = match self.server {
+            Some(ref server) if !self.misses.borrow().contains(&id) => server,
+            _ => return Ok(None),
+        };
+        let (path, downloaded) = match server.fetch(&id)? {
+            Some(fetched) => fetched,
+            None => {
+                self.misses.borrow_mut().insert(id);
+                return Ok(None);
+            }
+        };
+        let (_, macho, dwarf) = match scan_file(&path)?.into_iter().find(|(object_id, _, _)| *object_id == id) {
+            Some(found) => found,
+            None => {
+                // keep it from being indexed under its real id later
+                if downloaded {
+                    server.evict(&id)?;
+                }
+                return Err(anyhow!("symbol server returned an object with a different id for {}", id));
+            }
+        };
+        let entry = StoreEntry {
+            module_path: path.clone(),
+            path,
+            macho,
+            dwarf,
+        };
+        self.entries.borrow_mut().insert(id, entry.clone());
+        Ok(Some(entry))
+    }
+
+    pub fn symbolicate(
+        &self,
+        id: &str,
+        load_address: u64,
+        addresses: Vec<u64>,
+        file_offset_type: bool,
+    ) -> Result<Vec<ResponseResult>> {
+        let entry = self
+            .lookup(id)?
+            .ok_or_else(|| anyhow!("no object with id {} in symbol store", id))?;
+        let uuid = if entry.macho { Some(id) } else { None };
+        Symbolicator::open_as(&entry.path, &entry.module_path, None, uuid)?.symbolicate(
+            load_address,
+            addresses,
+            file_offset_type,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::remote::ServerLayout;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    // the build-ids of tests/fixtures/libfixture-dwarf4.so and -dwarf5.so
+    const DWARF4_ID: &str = "325930c47972521538ad1444f80e30e840565705";
+    const DWARF5_ID: &str = "cb96879276038671d25a64e0306b28317c7624ed";
+
+    fn fixture(name: &str) -> Vec<u8> {
+        fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name)).unwrap()
+    }
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("atosl-store-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // serves `files` by path over HTTP, with the length to announce for
+    // each, 404 for anything else; returns the url and the paths asked for
+    fn serve(files: Vec<(String, usize, Vec<u8>)>) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let log = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let path = line.split(' ').nth(1).unwrap_or("").to_string();
+                log.lock().unwrap().push(path.clone());
+                loop {
+                    let mut header = String::new();
+                    if reader.read_line(&mut header).unwrap() == 0 || header.trim().is_empty() {
+                        break;
+                    }
+                }
+                match files.iter().find(|(file, _, _)| *file == path) {
+                    Some((_, length, data)) => {
+                        let head = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", length);
+                        stream.write_all(head.as_bytes()).unwrap();
+                        stream.write_all(data).unwrap();
+                    }
+                    None => {
+                        let head = "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+                        stream.write_all(head.as_bytes()).unwrap();
+                    }
+                }
+            }
+        });
+        (url, requests)
+    }
+
+    fn served(path: String, data: Vec<u8>) -> (String, usize, Vec<u8>) {
+        (path, data.len(), data)
+    }
+
+    fn open_store(dir: &Path, url: String, layout: ServerLayout) -> SymbolStore {
+        let server = SymbolServer {
+            url,
+            layout,
+            cache_dir: dir.join(".atosl-cache"),
+            cache_size: None,
+        };
+        SymbolStore::open(dir.to_str().unwrap(), Some(server)).unwrap()
+    }
+
+    #[test]
+    fn caches_downloads_under_the_object_name() {
+        let dir = scratch_dir("debuginfod");
+        let (url, requests) = serve(vec![served(
+            format!("/buildid/{}/debuginfo", DWARF4_ID),
+            fixture("libfixture-dwarf4.so"),
+        )]);
+        let store = open_store(&dir, url, ServerLayout::Debuginfod);
+        let entry = store.lookup(DWARF4_ID).unwrap().unwrap();
+        let path = dir.join(".atosl-cache").join(DWARF4_ID).join("libfixture.so");
+        assert_eq!(entry.path, path);
+        assert_eq!(entry.module_path, path);
+        assert!(entry.dwarf && !entry.macho);
+
+        // a reopened store finds the download in its cache directory
+        let store = SymbolStore::open(dir.to_str().unwrap(), None).unwrap();
+        assert_eq!(store.lookup(DWARF4_ID).unwrap().unwrap().path, path);
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn records_ids_the_server_does_not_have() {
+        let dir = scratch_dir("misses");
+        let (url, requests) = serve(Vec::new());
+        let store = open_store(&dir, url, ServerLayout::Ssqp);
+        let uuid = "0123456789abcdef0123456789abcdef";
+        assert!(store.lookup(uuid).unwrap().is_none());
+        assert!(store.lookup(uuid).unwrap().is_none());
+        // a Mach-O uuid is tried as a dSYM and as an ELF debug file, once
+        assert_eq!(
+            *requests.lock().unwrap(),
+            vec![
+                format!("/_.dwarf/mach-uuid-sym-{}/_.dwarf", uuid),
+                format!("/_.debug/elf-buildid-sym-{}/_.debug", uuid),
+            ]
+        );
+        assert!(!dir.join(".atosl-cache").join(uuid).exists());
+    }
+
+    #[test]
+    fn deletes_downloads_with_another_id() {
+        let dir = scratch_dir("mismatch");
+        let (url, _) = serve(vec![
+            served(
+                format!("/_.debug/elf-buildid-sym-{}/_.debug", DWARF5_ID),
+                fixture("libfixture-dwarf4.so"),
+            ),
+            served(
+                format!("/_.debug/elf-buildid-sym-{}/_.debug", DWARF4_ID),
+                fixture("libfixture-dwarf4.so"),
+            ),
+        ]);
+        let store = open_store(&dir, url, ServerLayout::Ssqp);
+        let err = store.lookup(DWARF5_ID).err().unwrap();
+        assert!(err.to_string().contains("different id"), "{}", err);
+        assert!(!dir.join(".atosl-cache").join(DWARF5_ID).exists());
+        assert!(store.lookup(DWARF4_ID).unwrap().is_some());
+    }
+
+    #[test]
+    fn removes_partial_downloads() {
+        let dir = scratch_dir("partial");
+        let data = fixture("libfixture-dwarf4.so");
+        // the connection closes before the announced length is sent
+        let (url, _) = serve(vec![(format!("/buildid/{}/debuginfo", DWARF4_ID), data.len() * 2, data)]);
+        let store = open_store(&dir, url, ServerLayout::Debuginfod);
+        assert!(store.lookup(DWARF4_ID).is_err());
+        assert!(!dir.join(".atosl-cache").join(DWARF4_ID).exists());
+    }
+
+    #[test]
+    fn trims_the_cache_but_keeps_the_download() {
+        let dir = scratch_dir("trim");
+        let (url, requests) = serve(vec![served(
+            format!("/buildid/{}/debuginfo", DWARF5_ID),
+            fixture("libfixture-dwarf5.so"),
+        )]);
+        let cache_dir = dir.join("cache");
+        let old = cache_dir.join("00ff");
+        fs::create_dir_all(&old).unwrap();
+        fs::write(old.join("libold.so"), [0; 64]).unwrap();
+        // smaller than the download alone
+        let server = SymbolServer {
+            url,
+            layout: ServerLayout::Debuginfod,
+            cache_dir: cache_dir.clone(),
+            cache_size: Some(1),
+        };
+        let (path, downloaded) = server.fetch(DWARF5_ID).unwrap().unwrap();
+        assert!(downloaded);
+        assert_eq!(path, cache_dir.join(DWARF5_ID).join("libfixture.so"));
+        assert!(path.is_file());
+        assert!(!old.exists());
+        assert_eq!(server.fetch(DWARF5_ID).unwrap().unwrap(), (path, false));
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+}
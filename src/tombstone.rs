@@ -0,0 +1,187 @@
+use crate::atosl::{self, parse_hex_address, GroupAddress};
+use crate::elf::{self, SharedObjects};
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+// a backtrace line of a tombstone, or of logcat with its prefix, e.g.
+// A DEBUG   :       #00 pc 000000000004a2b0  /data/app/~~x/lib/arm64/libflutter.so (BuildId: 1a2b...)
+struct BacktraceFrame<'a> {
+    // length of the line up to the end of the library path
+    path_end: usize,
+    pc: u64,
+    name: &'a str,
+    build_id: Option<Vec<u8>>,
+}
+
+fn parse_backtrace_line(line: &str) -> Option<BacktraceFrame<'_>> {
+    let frame_start = line.find('#')?;
+    let mut tokens = line[frame_start..].split_whitespace();
+    let index = tokens.next()?;
+    if index.len() < 2 || !index[1..].chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    if tokens.next()? != "pc" {
+        return None;
+    }
+    let pc = parse_hex_address(tokens.next()?).ok()?;
+    let path = tokens.next()?;
+    // tokens are subslices of `line`
+    let path_end = path.as_ptr() as usize - line.as_ptr() as usize + path.len();
+    // libraries loaded straight from an apk are printed as base.apk!libfoo.so
+    let name = path.rsplit(['/', '!']).next()?;
+    let rest = &line[path_end..];
+    let build_id = rest.find("(BuildId: ").and_then(|start| {
+        let build_id = &rest[start + "(BuildId: ".len()..];
+        elf::parse_build_id(&build_id[..build_id.find(')')?]).ok()
+    });
+    Some(BacktraceFrame {
+        path_end,
+        pc,
+        name,
+        build_id,
+    })
+}
+
+/// Symbolicates the `#NN pc` frames of an Android tombstone or logcat crash
+/// whose library is found below `so_dir`, matched by GNU build-id when the
+/// frame has one and by file name otherwise. Returns the backtrace with those
+/// frames annotated; other lines, and frames of libraries that fail to
+/// load, are kept as they are.
+pub fn symbolicate_tombstone(report: &str, so_dir: &str) -> Result<String> {
+    let shared_objects = SharedObjects::index(Path::new(so_dir))?;
+
+    // frame pcs grouped by the library they resolve to
+    let mut library_pcs: HashMap<PathBuf, Vec<u64>> = HashMap::new();
+    let mut frame_count = 0;
+    for line in report.lines() {
+        if let Some(frame) = parse_backtrace_line(line) {
+            frame_count += 1;
+            if let Some(path) = shared_objects.find(frame.name, frame.build_id.as_deref()) {
+                let pcs = library_pcs.entry(path.clone()).or_default();
+                if !pcs.contains(&frame.pc) {
+                    pcs.push(frame.pc);
+                }
+            }
+        }
+    }
+    if frame_count == 0 {
+        return Err(anyhow!("no backtrace frames found in tombstone"));
+    }
+
+    let mut symbolicated: HashMap<(PathBuf, u64), String> = HashMap::new();
+    for (path, pcs) in library_pcs {
+        let results = match atosl::parse_file_addresses(
+            &path.to_string_lossy(),
+            None,
+            None,
+            &[],
+            vec![GroupAddress {
+                load_address: 0,
+                addresses: pcs,
+                uuid: None,
+            }],
+            // tombstone pcs are already relative to the library's load bias,
+            // that is virtual addresses of the ELF file
+            false,
+        ) {
+            Ok(results) => results,
+            // a corrupt library only leaves its own frames unsymbolicated
+            Err(_) => continue,
+        };
+        // unresolved addresses keep their original line
+        for result in results.into_iter().filter(|result| result.error.is_none()) {
+            symbolicated.insert((path.clone(), result.address), result.result);
+        }
+    }
+
+    let mut output = String::with_capacity(report.len());
+    for line in report.lines() {
+        let annotated = parse_backtrace_line(line).and_then(|frame| {
+            let path = shared_objects.find(frame.name, frame.build_id.as_deref())?;
+            let symbol = symbolicated.get(&(path.clone(), frame.pc))?;
+            Some((frame, symbol))
+        });
+        match annotated {
+            Some((frame, symbol)) => {
+                // e.g. `(offset 0x…)`, the symbol the device printed and the
+                // BuildId stay after the inserted symbol
+                output.push_str(&line[..frame.path_end]);
+                output.push_str(" (");
+                output.push_str(symbol);
+                output.push(')');
+                output.push_str(&line[frame.path_end..]);
+            }
+            None => output.push_str(line),
+        }
+        output.push('\n');
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn parses_tombstone_frames() {
+        let line = "      #00 pc 000000000004a2b0  /data/app/~~x/lib/arm64/libflutter.so (BuildId: 1a2b)";
+        let frame = parse_backtrace_line(line).unwrap();
+        assert_eq!(frame.pc, 0x4a2b0);
+        assert_eq!(frame.name, "libflutter.so");
+        assert_eq!(frame.build_id, Some(vec![0x1a, 0x2b]));
+        assert_eq!(&line[frame.path_end..], " (BuildId: 1a2b)");
+    }
+
+    #[test]
+    fn parses_logcat_and_apk_frames() {
+        let line = concat!(
+            "10-18 10:00:00.000  1234  1234 F DEBUG   :       ",
+            "#01 pc 0x1112  /data/app/base.apk!libt.so (offset 0x4000) (func+96)"
+        );
+        let frame = parse_backtrace_line(line).unwrap();
+        assert_eq!(frame.pc, 0x1112);
+        assert_eq!(frame.name, "libt.so");
+        assert_eq!(frame.build_id, None);
+        assert_eq!(&line[frame.path_end..], " (offset 0x4000) (func+96)");
+    }
+
+    #[test]
+    fn ignores_invalid_build_ids() {
+        let frame = parse_backtrace_line("#00 pc 10  /system/lib64/libc.so (BuildId: 123)").unwrap();
+        assert_eq!(frame.build_id, None);
+    }
+
+    #[test]
+    fn rejects_other_lines() {
+        assert!(parse_backtrace_line("backtrace:").is_none());
+        assert!(parse_backtrace_line("    #00 sp 0000007fc0a1b2c0").is_none());
+        assert!(parse_backtrace_line("    #xx pc 000000000004a2b0  /system/lib64/libc.so").is_none());
+        assert!(parse_backtrace_line("    #00 pc zz  /system/lib64/libc.so").is_none());
+        assert!(parse_backtrace_line("    #00 pc 000000000004a2b0").is_none());
+    }
+
+    #[test]
+    fn skips_libraries_that_fail_to_load() {
+        let dir = std::env::temp_dir().join(format!("atosl-tombstone-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/libfixture-dwarf5.so");
+        fs::copy(fixture, dir.join("libfixture.so")).unwrap();
+        // an ELF header and nothing else
+        fs::write(dir.join("libbroken.so"), b"\x7fELF\x02\x01\x01").unwrap();
+        let report = concat!(
+            "backtrace:\n",
+            "      #00 pc 0000000000001178  /data/app/lib/arm64/libbroken.so\n",
+            "      #01 pc 0000000000001178  /data/app/lib/arm64/libfixture.so (BuildId: cb96879276038671d25a64e0306b28317c7624ed)\n",
+        );
+        let output = symbolicate_tombstone(report, dir.to_str().unwrap()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[1], "      #00 pc 0000000000001178  /data/app/lib/arm64/libbroken.so");
+        assert_eq!(
+            lines[2],
+            "      #01 pc 0000000000001178  /data/app/lib/arm64/libfixture.so (hot (in libfixture.so) (fixture.c:28)) (BuildId: cb96879276038671d25a64e0306b28317c7624ed)"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,12 @@
+#!/bin/sh
+# Rebuilds the ELF fixtures of the unit tests from fixture.c. The tests use
+# the addresses gcc 12 on x86_64 gives the code, check them against
+# `objdump -d` when the fixtures are rebuilt.
+set -e
+cd "$(dirname "$0")"
+for version in 4 5; do
+    gcc -shared -fPIC -O2 -g -gdwarf-$version -Wl,--build-id -Wl,-soname,libfixture.so \
+        -o libfixture-dwarf$version.so fixture.c
+done
+objcopy --remove-section .debug_aranges libfixture-dwarf5.so libfixture-noaranges.so
+strip --strip-debug -o libfixture-nodebug.so libfixture-dwarf5.so
@@ -0,0 +1,29 @@
+#include <stdlib.h>
+
+volatile int sink;
+
+static inline __attribute__((always_inline)) int inner(int x) {
+    sink = x;
+    return x * 7 + 3;
+}
+
+static inline __attribute__((always_inline)) int middle(int x) {
+    return inner(x) + 1;
+}
+
+__attribute__((noinline)) int outer(int x) {
+    return middle(x) * 2;
+}
+
+__attribute__((noinline, noreturn, cold)) void fail(int x) {
+    sink = x;
+    abort();
+}
+
+__attribute__((noinline)) int hot(int x) {
+    if (__builtin_expect(x < 0, 0)) {
+        sink = x * 3;
+        fail(x);
+    }
+    return x + 1;
+}